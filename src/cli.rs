@@ -0,0 +1,651 @@
+//! The command line interface shared by the `rpn` and `infix` binaries.
+
+use atty::Stream;
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::{
+    format::{Format, Notation},
+    infix,
+    math::Value,
+    rpn::{self, Interpreter},
+    utils::{strip_comment, LineError},
+};
+
+/// A notation the calculator can read
+pub struct Language {
+    pub name: &'static str,
+    /// Evaluates a line, returning the error to show if it fails, at the
+    /// token it's about, or nothing if there's no result to show, like for a
+    /// definition
+    pub eval: fn(&Session, &str) -> Result<Option<Value>, LineError>,
+    /// Whether a line goes on on the next one, like after an open parenthesis
+    pub is_incomplete: fn(&str) -> bool,
+    /// The file a line like `import "stats.inf"` imports, if it's an import
+    pub parse_import: fn(&str) -> Option<String>,
+    /// Definitions run at startup, unless the `--no-prelude` flag is given
+    pub prelude: &'static str,
+}
+
+impl Language {
+    /// RPN, as the `rpn` binary reads it
+    pub fn rpn() -> Language {
+        Language {
+            name: "RPN",
+            eval: eval_rpn,
+            is_incomplete: rpn::is_incomplete,
+            parse_import: rpn::parse_include,
+            prelude: rpn::PRELUDE,
+        }
+    }
+
+    /// Infix, as the `infix` binary reads it
+    pub fn infix() -> Language {
+        Language {
+            name: "Infix",
+            eval: eval_infix,
+            is_incomplete: infix::is_incomplete,
+            parse_import: infix::parse_import,
+            prelude: infix::PRELUDE,
+        }
+    }
+}
+
+fn eval_rpn(session: &Session, line: &str) -> Result<Option<Value>, LineError> {
+    let (parsed, offsets) =
+        rpn::parse_rpn_with_offsets(line).map_err(|e| e.context("Error parsing RPN"))?;
+    session
+        .interpreter
+        .execute_line_with_offsets(&parsed, &offsets)
+        .map_err(|e| e.context("Error executing RPN"))
+}
+
+fn eval_infix(session: &Session, line: &str) -> Result<Option<Value>, LineError> {
+    let (parsed, offsets) =
+        infix::parse_infix_with_offsets(line).map_err(|e| e.context("Error parsing infix"))?;
+    let interpreter = &session.interpreter;
+    let strict = session.strict;
+    if let Some(defined) = infix::define_function(&parsed, &offsets, line, interpreter, strict) {
+        defined.map_err(|e| e.context("Error converting to RPN"))?;
+        return Ok(None);
+    }
+    let (rpn, offsets) = infix::infix_to_rpn_with(&parsed, &offsets, interpreter, strict)
+        .map_err(|e| e.context("Error converting to RPN"))?;
+    interpreter
+        .execute_with_offsets(&rpn, &offsets)
+        .map(Some)
+        .map_err(|e| e.context("Error executing RPN"))
+}
+
+/// The settings of a run of a binary, which can be changed with flags like
+/// `--hex` or REPL commands like `:hex`
+#[derive(Default)]
+pub struct Session {
+    pub interpreter: Interpreter,
+    pub format: Format,
+    /// Makes juxtaposition like `2x` an error in infix, instead of
+    /// multiplication
+    pub strict: bool,
+    /// The scripts being run, innermost last, which imports are relative to,
+    /// as they were named and as absolute paths
+    running: Vec<(PathBuf, PathBuf)>,
+    /// The scripts run so far, which aren't run again when imported
+    loaded: HashSet<PathBuf>,
+}
+
+impl Session {
+    /// Runs a command like `hex` or `word u8`
+    pub fn command(&mut self, name: &str, arg: Option<&str>) -> Result<(), String> {
+        match (name, arg) {
+            ("dec" | "hex" | "oct" | "bin", None) => self.format.radix = name.parse()?,
+            ("word", Some("off")) => {
+                self.interpreter.word_size = None;
+                self.format.word_size = None;
+            }
+            ("word", Some(size)) => {
+                let size = size.parse()?;
+                self.interpreter.word_size = Some(size);
+                self.format.word_size = Some(size);
+            }
+            ("word", None) => match self.format.word_size {
+                Some(size) => println!("{}", size),
+                None => println!("off"),
+            },
+            ("auto", None) => self.format.notation = Notation::Auto,
+            ("sci", None) => self.format.notation = Notation::Scientific,
+            ("eng", None) => self.format.notation = Notation::Engineering,
+            ("fix", Some(decimals)) => {
+                self.format.notation = Notation::Fixed(parse_count(decimals)?);
+            }
+            ("sig", Some("off")) => self.format.digits = None,
+            ("sig", Some(digits)) => match parse_count(digits)? {
+                0 => return Err(format!("Invalid Count: {}", digits)),
+                digits => self.format.digits = Some(digits),
+            },
+            ("thousands", None | Some("on")) => self.format.thousands = true,
+            ("thousands", Some("off")) => self.format.thousands = false,
+            ("comma", None | Some("on")) => self.format.decimal_comma = true,
+            ("comma", Some("off")) => self.format.decimal_comma = false,
+            ("strict", None | Some("on")) => self.strict = true,
+            ("strict", Some("off")) => self.strict = false,
+            ("ieee", Some("strict")) => self.interpreter.strict_ieee = true,
+            ("ieee", Some("permissive")) => self.interpreter.strict_ieee = false,
+            ("ieee", Some(mode)) => return Err(format!("Invalid Mode: {}", mode)),
+            ("ieee", None) => match self.interpreter.strict_ieee {
+                true => println!("strict"),
+                false => println!("permissive"),
+            },
+            ("vars", None) => {
+                let words = self.interpreter.words.borrow();
+                let mut names: Vec<_> = words.keys().collect();
+                names.sort();
+                for name in names {
+                    println!("{}", words[name].source);
+                }
+            }
+            _ => return Err(format!("Invalid Command: {}", name)),
+        }
+        Ok(())
+    }
+
+    /// Whether a command takes an argument
+    fn takes_arg(name: &str) -> bool {
+        matches!(name, "word" | "fix" | "sig" | "ieee")
+    }
+
+    fn eval(&self, language: &Language, line: &str) -> Result<(), LineError> {
+        let result = (language.eval)(self, line);
+        for warning in self.interpreter.warnings.take() {
+            eprintln!("Warning: {}", warning);
+        }
+        if let Some(result) = result? {
+            println!("{}", self.format.format(&result));
+        }
+        Ok(())
+    }
+
+    /// Evaluates a line, or runs it if it's a REPL command like `:hex` or an
+    /// import
+    fn line(&mut self, language: &Language, line: &str) -> Result<(), LineError> {
+        if let Some(path) = (language.parse_import)(line) {
+            return Ok(self.import(language, &path)?);
+        }
+        match command(line) {
+            Some(command) => {
+                let mut words = command.split_whitespace();
+                let name = words.next().unwrap();
+                Ok(self.command(name, words.next())?)
+            }
+            None => self.eval(language, line),
+        }
+    }
+
+    /// Like [`Session::line`], showing the error if there's one
+    fn line_or_report(&mut self, language: &Language, line: &str) {
+        if let Err(e) = self.line(language, line) {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// Runs a script relative to the one importing it, unless it's been run
+    /// already
+    fn import(&mut self, language: &Language, path: &str) -> Result<(), String> {
+        let path = match self.running.last().and_then(|(script, _)| script.parent()) {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        };
+        if self.loaded.contains(&canonicalize(&path)?) {
+            return Ok(());
+        }
+        self.run_file(language, &path)
+    }
+
+    /// Runs a script, stopping at the first error, which is reported with the
+    /// file, line and column of the token it's about
+    pub fn run_file(&mut self, language: &Language, path: &Path) -> Result<(), String> {
+        let canonical = canonicalize(path)?;
+        if self
+            .running
+            .iter()
+            .any(|(_, running)| *running == canonical)
+        {
+            return Err(format!("Circular Import: {}", path.display()));
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        self.running.push((path.to_path_buf(), canonical.clone()));
+        let result = self.run_script(language, path, &text);
+        self.running.pop();
+        // a script that failed can be imported again once it's fixed
+        if result.is_ok() {
+            self.loaded.insert(canonical);
+        }
+        result
+    }
+
+    /// Runs the definitions of the language's prelude
+    pub fn run_prelude(&mut self, language: &Language) -> Result<(), String> {
+        self.run_script(language, Path::new("prelude"), language.prelude)
+    }
+
+    fn run_script(&mut self, language: &Language, path: &Path, text: &str) -> Result<(), String> {
+        let mut statements = Statements::default();
+        let report = |statement: Statement, e: LineError| {
+            let (line, column) = statement.position(e.offset);
+            format!("{}:{}:{}: {}", path.display(), line, column, e)
+        };
+        for (number, line) in text.lines().enumerate() {
+            if let Some(statement) = statements.push(language, number + 1, line) {
+                if let Err(e) = self.line(language, &statement.text) {
+                    return Err(report(statement, e));
+                }
+            }
+        }
+        if let Some(statement) = statements.finish() {
+            if let Err(e) = self.line(language, &statement.text) {
+                return Err(report(statement, e));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The absolute path of a file, which is the same however it's imported
+fn canonicalize(path: &Path) -> Result<PathBuf, String> {
+    fs::canonicalize(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))
+}
+
+/// The REPL command in a line like `:hex`, if it's one
+fn command(line: &str) -> Option<&str> {
+    line.strip_prefix(':')
+        .filter(|c| c.starts_with(|c: char| c.is_alphabetic()))
+}
+
+/// An expression or command, which can span several lines
+struct Statement {
+    text: String,
+    /// Where it starts, counting from 1
+    line: usize,
+    /// The column of its first character, after any indentation
+    column: usize,
+}
+
+impl Statement {
+    /// The line and column of the byte at `offset` in its text, which errors
+    /// are reported at
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset];
+        match before.rfind('\n') {
+            Some(newline) => (
+                self.line + before.matches('\n').count(),
+                before[newline + 1..].chars().count() + 1,
+            ),
+            None => (self.line, self.column + before.chars().count()),
+        }
+    }
+}
+
+/// Joins lines into statements, leaving out comments and blank lines
+#[derive(Default)]
+struct Statements {
+    /// The lines of the statement read so far
+    current: Option<Statement>,
+}
+
+impl Statements {
+    /// Adds a line, returning the statement it completes, if any
+    fn push(&mut self, language: &Language, number: usize, line: &str) -> Option<Statement> {
+        let line = strip_comment(line);
+        if line.trim().is_empty() {
+            return None;
+        }
+        let statement = match self.current.take() {
+            Some(mut statement) => {
+                statement.text.push('\n');
+                statement.text.push_str(line);
+                statement
+            }
+            None => Statement {
+                text: line.trim_start().to_string(),
+                line: number,
+                column: line.chars().take_while(|c| c.is_whitespace()).count() + 1,
+            },
+        };
+        // commands are always one line
+        if command(&statement.text).is_none() && (language.is_incomplete)(&statement.text) {
+            self.current = Some(statement);
+            return None;
+        }
+        Some(statement)
+    }
+
+    /// Whether a statement goes on on the next line
+    fn is_incomplete(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Returns the statement that's still incomplete at the end of the input
+    fn finish(&mut self) -> Option<Statement> {
+        self.current.take()
+    }
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("Invalid Count: {}", s))
+}
+
+pub fn main(language: Language) {
+    let mut session = Session::default();
+    let mut args = env::args().skip(1).peekable();
+
+    let flags = match parse_flags(&mut session, &mut args) {
+        Ok(flags) => flags,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Try --help to list the flags");
+            process::exit(2);
+        }
+    };
+    if flags.help {
+        println!("{}", usage(&language));
+        return;
+    }
+
+    if flags.prelude {
+        if let Err(e) = session.run_prelude(&language) {
+            eprintln!("{}", e);
+        }
+    }
+
+    // `run file` runs a script
+    if args.next_if(|arg| arg == "run").is_some() {
+        let Some(path) = args.next() else {
+            eprintln!("Missing File after run");
+            process::exit(2);
+        };
+        if let Err(e) = session.run_file(&language, Path::new(&path)) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let args: Vec<String> = args.collect();
+    if !args.is_empty() {
+        let thing = args.join(" ");
+        if let Err(e) = session.eval(&language, &thing) {
+            eprintln!("{}", e);
+        }
+    } else if atty::is(Stream::Stdin) {
+        repl(&language, &mut session);
+    } else {
+        let stdin = io::stdin();
+        let mut statements = Statements::default();
+        for (number, line) in stdin.lock().lines().enumerate() {
+            if let Some(statement) = statements.push(&language, number + 1, &line.unwrap()) {
+                session.line_or_report(&language, &statement.text);
+            }
+        }
+        if let Some(statement) = statements.finish() {
+            session.line_or_report(&language, &statement.text);
+        }
+    }
+}
+
+/// What the flags before the expression asked for, other than settings
+#[derive(Debug, PartialEq)]
+struct Flags {
+    prelude: bool,
+    help: bool,
+}
+
+/// Applies the flags at the start of `args`, like `--hex` and `--word u8`, to
+/// the session. A flag's value is only taken if it's valid, so a mistake like
+/// `--ieee 1/0` is reported rather than run as the expression.
+fn parse_flags(
+    session: &mut Session,
+    args: &mut Peekable<impl Iterator<Item = String>>,
+) -> Result<Flags, String> {
+    let mut flags = Flags {
+        prelude: true,
+        help: false,
+    };
+    while let Some(flag) = args.next_if(|arg| arg.starts_with("--")) {
+        let name = &flag[2..];
+        match name {
+            "no-prelude" => flags.prelude = false,
+            "help" => flags.help = true,
+            _ if Session::takes_arg(name) => {
+                let Some(arg) = args.peek().cloned() else {
+                    return Err(format!("Missing Value after --{}", name));
+                };
+                session.command(name, Some(&arg))?;
+                args.next();
+            }
+            _ => session
+                .command(name, None)
+                .map_err(|_| format!("Invalid Flag: --{}", name))?,
+        }
+    }
+    Ok(flags)
+}
+
+fn usage(language: &Language) -> String {
+    let name = language.name.to_lowercase();
+    [
+        format!("Usage: {} [flags] [expression | run file]", name),
+        "Flags:".into(),
+        "  --hex, --oct, --bin, --dec  the radix numbers are shown in".into(),
+        "  --word size                 the word size, like i8 or u64 (or off)".into(),
+        "  --sig digits, --fix decimals, --sci, --eng, --auto".into(),
+        "                              the notation numbers are shown in".into(),
+        "  --thousands, --comma        the separators numbers are shown with".into(),
+        "  --strict                    makes '2x' an error instead of '2 * x'".into(),
+        "  --ieee mode                 strict makes '1/0' an error (or permissive)".into(),
+        "  --no-prelude                leaves out the prelude's definitions".into(),
+        "  --help                      shows this message".into(),
+        "Without an expression or a script, the REPL is started.".into(),
+    ]
+    .join("\n")
+}
+
+fn print_repl_help(language: &Language) {
+    println!("Welcome to the {} calculator!", language.name);
+    println!("Type 'quit' press Ctrl-D to exit.");
+    println!("Type ':hex', ':oct', ':bin' or ':dec' to change how numbers are shown,");
+    println!("':word i8' (or u8, i16, ..., u64, or off) to change the word size,");
+    println!("':sig 6', ':fix 2', ':sci', ':eng' or ':auto' to change the notation,");
+    println!("':thousands' or ':comma' (then 'off') to change the separators,");
+    println!("':strict' (then 'off') to make '2x' an error instead of '2 * x',");
+    println!("':ieee strict' (or permissive) to make '1/0' an error instead of 'inf',");
+    println!("and ':vars' to list the definitions.");
+}
+
+/// Shows `...` instead of `>` while a statement goes on
+fn print_repl_prompt(continued: bool) {
+    print!("{} ", if continued { "..." } else { ">" });
+    io::stdout().flush().unwrap();
+}
+
+fn repl(language: &Language, session: &mut Session) {
+    print_repl_help(language);
+    print_repl_prompt(false);
+    let stdin = io::stdin();
+    let mut statements = Statements::default();
+    for (number, line) in stdin.lock().lines().enumerate() {
+        if line.as_ref().unwrap() == "quit" {
+            break;
+        }
+        if let Some(statement) = statements.push(language, number + 1, &line.unwrap()) {
+            session.line_or_report(language, &statement.text);
+        }
+        print_repl_prompt(statements.is_incomplete());
+    }
+}
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_prelude() {
+        let eval = |language: &Language, line: &str| {
+            // the prelude has to load with --strict too
+            let mut session = Session {
+                strict: true,
+                ..Default::default()
+            };
+            session.run_prelude(language)?;
+            assert!(session.interpreter.warnings.take().is_empty());
+            (language.eval)(&session, line)
+        };
+        let number = |x| Ok(Some(Value::Number(x)));
+        let roots = Ok(Some(Value::List(vec![
+            Value::Number(1.0),
+            Value::Number(2.0),
+        ])));
+        assert_eq!(eval(&Language::infix(), "hypot(3, 4)"), number(5.0));
+        assert_eq!(eval(&Language::infix(), "clamp(5, 0, 3)"), number(3.0));
+        assert_eq!(eval(&Language::infix(), "lerp(10, 20, 0.25)"), number(12.5));
+        assert_eq!(
+            eval(&Language::infix(), "rad2deg(deg2rad(90))"),
+            number(90.0)
+        );
+        assert_eq!(
+            eval(&Language::infix(), "compound(100, 50%, 2)"),
+            number(225.0)
+        );
+        assert_eq!(eval(&Language::infix(), "roots(1, -3, 2)"), roots);
+        assert_eq!(eval(&Language::infix(), "len(roots(1, 0, 1))"), number(0.0));
+        assert_eq!(eval(&Language::rpn(), "3 4 hypot"), number(5.0));
+        assert_eq!(eval(&Language::rpn(), "-1 0 3 clamp"), number(0.0));
+        assert_eq!(eval(&Language::rpn(), "10 20 0.25 lerp"), number(12.5));
+        assert_eq!(eval(&Language::rpn(), "90 deg2rad rad2deg"), number(90.0));
+        assert_eq!(eval(&Language::rpn(), "100 50 % 2 compound"), number(225.0));
+        assert_eq!(eval(&Language::rpn(), "1 -3 2 roots"), roots);
+        assert_eq!(eval(&Language::rpn(), "1 0 1 roots len"), number(0.0));
+        let roots = Ok(Some(Value::List(vec![
+            Value::Number(-2.0),
+            Value::Number(2.0),
+        ])));
+        assert_eq!(eval(&Language::infix(), "roots(2, 0, -8)"), roots);
+        assert_eq!(eval(&Language::rpn(), "2 0 -8 roots"), roots);
+    }
+
+    #[test]
+    fn test_import() {
+        let language = Language::rpn();
+        let dir = env::temp_dir().join(format!("rpn-test-import-{}", process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        let write = |name: &str, text: &str| fs::write(dir.join(name), text).unwrap();
+        write(
+            "main.rpn",
+            "\"lib/geom.rpn\" include\n\"lib/geom.rpn\" include\n",
+        );
+        write(
+            "lib/geom.rpn",
+            "\"util.rpn\" include # relative to lib\n: area sq pi * ;\n",
+        );
+        write("lib/util.rpn", ": sq\n  dup * ;\n");
+        write("a.rpn", "\"b.rpn\" include\n");
+        write("b.rpn", "# includes a\n\"a.rpn\" include\n");
+
+        let mut session = Session::default();
+        assert_eq!(session.run_file(&language, &dir.join("main.rpn")), Ok(()));
+        assert!(session.interpreter.words.borrow().contains_key("area"));
+        // each file is run once, so nothing was redefined
+        assert!(session.interpreter.warnings.take().is_empty());
+
+        let a = dir.join("a.rpn");
+        assert_eq!(
+            session.run_file(&language, &a),
+            Err(format!(
+                "{}:1:1: {}:2:1: Circular Import: {}",
+                a.display(),
+                dir.join("b.rpn").display(),
+                a.display()
+            ))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_script_errors() {
+        let dir = env::temp_dir().join(format!("rpn-test-errors-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let run = |language: &Language, name: &str, text: &str| {
+            let path = dir.join(name);
+            fs::write(&path, text).unwrap();
+            let result = Session::default().run_file(language, &path);
+            result.map_err(|e| e.replace(&format!("{}:", path.display()), ""))
+        };
+        assert_eq!(
+            run(&Language::rpn(), "a.rpn", "# sums\n1 2 +\n  3 foo +\n"),
+            Err("3:5: Error executing RPN: unknown word: foo".into())
+        );
+        assert_eq!(
+            run(&Language::rpn(), "b.rpn", "[ 1 2\n  3 ] [\n"),
+            Err("2:7: Error executing RPN: invalid syntax: unmatched [".into())
+        );
+        assert_eq!(
+            run(&Language::infix(), "a.inf", "x = 1 +\n  (2 * $)\n"),
+            Err("2:8: Error parsing infix: Invalid Operator: $".into())
+        );
+        assert_eq!(
+            run(&Language::infix(), "b.inf", "  \"é\" + len(1)\n"),
+            Err("1:9: Error executing RPN: invalid type: len".into())
+        );
+        assert_eq!(
+            run(&Language::infix(), "c.inf", "f(x) = x +\n  ]\n"),
+            Err("2:3: Error converting to RPN: Mismatched Brackets".into())
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_flags() {
+        let parse = |args: &[&str]| {
+            let mut session = Session::default();
+            let mut args = args.iter().map(|arg| arg.to_string()).peekable();
+            let flags = parse_flags(&mut session, &mut args);
+            (flags, session, args.collect::<Vec<_>>())
+        };
+        let (flags, session, rest) = parse(&["--ieee", "strict", "--hex", "1/0"]);
+        assert_eq!(
+            flags,
+            Ok(Flags {
+                prelude: true,
+                help: false
+            })
+        );
+        assert!(session.interpreter.strict_ieee);
+        assert_eq!(rest, ["1/0"]);
+        let (flags, _, rest) = parse(&["--no-prelude", "--help"]);
+        assert_eq!(
+            flags,
+            Ok(Flags {
+                prelude: false,
+                help: true
+            })
+        );
+        assert!(rest.is_empty());
+        // the value isn't taken if it's wrong
+        let (flags, _, rest) = parse(&["--ieee", "1/0"]);
+        assert_eq!(flags, Err("Invalid Mode: 1/0".into()));
+        assert_eq!(rest, ["1/0"]);
+        assert_eq!(
+            parse(&["--word"]).0,
+            Err("Missing Value after --word".into())
+        );
+        assert_eq!(
+            parse(&["--hexx", "1"]).0,
+            Err("Invalid Flag: --hexx".into())
+        );
+    }
+}
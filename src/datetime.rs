@@ -0,0 +1,476 @@
+//! Dates, times and durations in UTC or a fixed offset from it, with ISO-8601
+//! literals like `2026-10-17`, `2026-10-17T09:30:00+02:00` and `PT3H30M`.
+
+use std::{fmt::Display, str::FromStr};
+
+const MINUTE: f64 = 60.0;
+const HOUR: f64 = 3600.0;
+const DAY: f64 = 86400.0;
+
+/// 0000-01-01T00:00:00Z and 10000-01-01T00:00:00Z, the instants between which
+/// a [`DateTime`] can be, so its year always has four digits
+const MIN_SECONDS: f64 = -62167219200.0;
+const MAX_SECONDS: f64 = 253402300800.0;
+/// Longest duration, the span of the instants a [`DateTime`] can be
+pub const MAX_DURATION: f64 = MAX_SECONDS - MIN_SECONDS;
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// An instant, shown in a fixed offset from UTC
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+    /// Seconds since 1970-01-01T00:00:00Z, from the year 0 to 9999
+    pub seconds: f64,
+    /// Seconds east of UTC that the instant is shown in
+    pub offset: i32,
+}
+
+/// The calendar fields of a [`DateTime`] in its offset
+struct Fields {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: f64,
+    /// Days since Monday
+    weekday: usize,
+    /// Days since the start of the year
+    yearday: u32,
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    // days since March 1st, so leap days are at the end of the year
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date `days` days after 1970-01-01, the inverse of [`days_from_civil`]
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// Exactly `n` ASCII digits at the start of `s`
+fn digits(s: &str, n: usize) -> Option<u32> {
+    let d = s.get(..n)?;
+    if d.bytes().all(|b| b.is_ascii_digit()) {
+        d.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Parses an offset like `Z`, `+02:00` or `-05:30` at the start of `s`,
+/// returning it in seconds and its length
+fn offset_prefix(s: &str) -> Option<(i32, usize)> {
+    let sign = match s.chars().next()? {
+        'Z' => return Some((0, 1)),
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let hours = digits(&s[1..], 2)?;
+    if s.get(3..4)? != ":" {
+        return None;
+    }
+    let minutes = digits(&s[4..], 2)?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some((sign * (hours * 3600 + minutes * 60) as i32, 6))
+}
+
+/// Parses an offset like `+02:00`, `Z` or `UTC`, in seconds
+pub fn parse_offset(s: &str) -> Option<i32> {
+    if s == "UTC" {
+        return Some(0);
+    }
+    match offset_prefix(s)? {
+        (offset, len) if len == s.len() => Some(offset),
+        _ => None,
+    }
+}
+
+impl DateTime {
+    /// Parses a date or date and time at the start of `s`, returning it and
+    /// its length
+    fn parse_prefix(s: &str) -> Option<(DateTime, usize)> {
+        let year = digits(s, 4)?;
+        let month = digits(s.get(5..)?, 2)?;
+        let day = digits(s.get(8..)?, 2)?;
+        if s.get(4..5)? != "-" || s.get(7..8)? != "-" {
+            return None;
+        }
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year as i64, month) {
+            return None;
+        }
+
+        let mut len = 10;
+        let mut time = 0.0;
+        let mut offset = 0;
+        if s[len..].starts_with('T') {
+            let hour = digits(&s[len + 1..], 2)?;
+            if s.get(len + 3..len + 4)? != ":" {
+                return None;
+            }
+            let minute = digits(&s[len + 4..], 2)?;
+            len += 6;
+            let mut second = 0.0;
+            if s[len..].starts_with(':') {
+                second = digits(&s[len + 1..], 2)? as f64;
+                len += 3;
+                if s[len..].starts_with('.') {
+                    let n = s[len + 1..].bytes().take_while(u8::is_ascii_digit).count();
+                    if n == 0 {
+                        return None;
+                    }
+                    second += s[len..len + n + 1].parse::<f64>().ok()?;
+                    len += n + 1;
+                }
+            }
+            if hour > 23 || minute > 59 || second >= 60.0 {
+                return None;
+            }
+            time = hour as f64 * HOUR + minute as f64 * MINUTE + second;
+            if let Some((o, n)) = offset_prefix(&s[len..]) {
+                offset = o;
+                len += n;
+            }
+        }
+
+        let days = days_from_civil(year as i64, month, day);
+        let seconds = days as f64 * DAY + time - offset as f64;
+        Some((DateTime { seconds, offset }, len))
+    }
+
+    /// The date and time `seconds` seconds later, if it's still between the
+    /// years 0 and 9999
+    pub fn plus(self, seconds: f64) -> Option<DateTime> {
+        let seconds = self.seconds + seconds;
+        (MIN_SECONDS..MAX_SECONDS)
+            .contains(&seconds)
+            .then_some(DateTime { seconds, ..self })
+    }
+
+    fn fields(&self) -> Fields {
+        // in range, since `seconds` is between the years 0 and 9999
+        let local = self.seconds + self.offset as f64;
+        let days = (local / DAY).floor();
+        let mut time = local - days * DAY;
+        let days = days as i64;
+        let (year, month, day) = civil_from_days(days);
+        let hour = (time / HOUR).floor();
+        time -= hour * HOUR;
+        let minute = (time / MINUTE).floor();
+        Fields {
+            year,
+            month,
+            day,
+            hour: hour as u32,
+            minute: minute as u32,
+            second: time - minute * MINUTE,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 3).rem_euclid(7) as usize,
+            yearday: (days - days_from_civil(year, 1, 1)) as u32,
+        }
+    }
+
+    pub fn weekday(&self) -> &'static str {
+        WEEKDAYS[self.fields().weekday]
+    }
+
+    /// Formats the date with `strftime`-like specifiers: `%Y`, `%y`, `%m`,
+    /// `%d`, `%j`, `%H`, `%M`, `%S`, `%A`, `%a`, `%B`, `%b`, `%z` and `%%`
+    pub fn format(&self, format: &str) -> Result<String, String> {
+        let f = self.fields();
+        let mut result = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                result.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => result += &f.year.to_string(),
+                Some('y') => result += &format!("{:02}", f.year.rem_euclid(100)),
+                Some('m') => result += &format!("{:02}", f.month),
+                Some('d') => result += &format!("{:02}", f.day),
+                Some('j') => result += &format!("{:03}", f.yearday + 1),
+                Some('H') => result += &format!("{:02}", f.hour),
+                Some('M') => result += &format!("{:02}", f.minute),
+                Some('S') => result += &format!("{:02}", f.second.floor()),
+                Some('A') => result += WEEKDAYS[f.weekday],
+                Some('a') => result += &WEEKDAYS[f.weekday][..3],
+                Some('B') => result += MONTHS[f.month as usize - 1],
+                Some('b') => result += &MONTHS[f.month as usize - 1][..3],
+                Some('z') => result += &format_offset(self.offset).replace(':', ""),
+                Some('%') => result.push('%'),
+                Some(c) => return Err(format!("invalid format: %{}", c)),
+                None => return Err("invalid format: %".into()),
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.unsigned_abs();
+    format!("{}{:02}:{:02}", sign, offset / 3600, offset / 60 % 60)
+}
+
+impl FromStr for DateTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match DateTime::parse_prefix(s) {
+            Some((datetime, len)) if len == s.len() => Ok(datetime),
+            _ => Err(format!("Invalid Date: {}", s)),
+        }
+    }
+}
+
+impl Display for DateTime {
+    /// Shows midnight UTC as just the date, and anything else in full
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields = self.fields();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}",
+            fields.year, fields.month, fields.day
+        )?;
+        let midnight = fields.hour == 0 && fields.minute == 0 && fields.second == 0.0;
+        if midnight && self.offset == 0 {
+            return Ok(());
+        }
+        write!(f, "T{:02}:{:02}:", fields.hour, fields.minute)?;
+        if fields.second < 10.0 {
+            write!(f, "0")?;
+        }
+        write!(f, "{}", fields.second)?;
+        match self.offset {
+            0 => write!(f, "Z"),
+            offset => write!(f, "{}", format_offset(offset)),
+        }
+    }
+}
+
+/// Parses a duration like `PT3H30M` or `P1W2D` at the start of `s`, returning
+/// it in seconds and its length. Years and months aren't supported, since
+/// their lengths vary.
+fn duration_prefix(s: &str) -> Option<(f64, usize)> {
+    if !s.starts_with('P') {
+        return None;
+    }
+    let mut len = 1;
+    let mut time = false;
+    let mut seconds = 0.0;
+    let mut parts = 0;
+    loop {
+        let rest = &s[len..];
+        if !time && rest.starts_with('T') {
+            time = true;
+            len += 1;
+            continue;
+        }
+        let n = rest
+            .bytes()
+            .take_while(|b| b.is_ascii_digit() || *b == b'.')
+            .count();
+        if n == 0 {
+            break;
+        }
+        let num = rest[..n].parse::<f64>().ok()?;
+        let unit = match (time, rest.as_bytes().get(n)?) {
+            (false, b'W') => 7.0 * DAY,
+            (false, b'D') => DAY,
+            (true, b'H') => HOUR,
+            (true, b'M') => MINUTE,
+            (true, b'S') => 1.0,
+            _ => return None,
+        };
+        seconds += num * unit;
+        len += n + 1;
+        parts += 1;
+    }
+    if parts == 0 || s[..len].ends_with('T') {
+        return None;
+    }
+    Some((seconds, len))
+}
+
+/// Parses a duration like `PT3H30M` or `-P1D`, in seconds, which can't be
+/// longer than [`MAX_DURATION`]
+pub fn parse_duration(s: &str) -> Result<f64, String> {
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, s),
+    };
+    match duration_prefix(rest) {
+        Some((seconds, len)) if len == rest.len() && seconds <= MAX_DURATION => Ok(sign * seconds),
+        _ => Err(format!("Invalid Duration: {}", s)),
+    }
+}
+
+/// Formats seconds as an ISO-8601 duration like `P1DT3H30M`
+pub fn format_duration(seconds: f64) -> String {
+    if seconds == 0.0 {
+        return "PT0S".into();
+    }
+    let mut result = if seconds < 0.0 { "-P" } else { "P" }.to_string();
+    let mut rest = seconds.abs();
+    let mut part = |unit: f64| {
+        let n = (rest / unit).floor();
+        rest -= n * unit;
+        n
+    };
+    let (days, hours, minutes) = (part(DAY), part(HOUR), part(MINUTE));
+    if days > 0.0 {
+        result += &format!("{}D", days);
+    }
+    if hours > 0.0 || minutes > 0.0 || rest > 0.0 {
+        result.push('T');
+    }
+    for (n, designator) in [(hours, 'H'), (minutes, 'M'), (rest, 'S')] {
+        if n > 0.0 {
+            result += &format!("{}{}", n, designator);
+        }
+    }
+    result
+}
+
+/// Length of the date or duration literal at the start of `s`, if there's one
+/// that isn't followed by more letters or digits
+pub fn literal_len(s: &str) -> Option<usize> {
+    let len = DateTime::parse_prefix(s)
+        .map(|(_, len)| len)
+        .or_else(|| duration_prefix(s).map(|(_, len)| len))?;
+    match s[len..].chars().next() {
+        Some(c) if c.is_alphanumeric() || c == '_' => None,
+        _ => Some(len),
+    }
+}
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_civil_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        for days in [-800000, -1, 0, 11016, 11017, 20000, 800000] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_datetime() {
+        let date: DateTime = "2026-10-17".parse().unwrap();
+        assert_eq!(date.to_string(), "2026-10-17");
+        assert_eq!(date.weekday(), "Saturday");
+        assert_eq!(date.plus(DAY * 15.0).unwrap().to_string(), "2026-11-01");
+        assert_eq!(days_from_civil(0, 1, 1) as f64 * DAY, MIN_SECONDS);
+        assert_eq!(days_from_civil(10000, 1, 1) as f64 * DAY, MAX_SECONDS);
+        assert_eq!(date.plus(1e20), None);
+        assert_eq!(date.plus(f64::NAN), None);
+
+        let time: DateTime = "2026-10-17T09:30:00+02:00".parse().unwrap();
+        assert_eq!(time.seconds, date.seconds + 7.5 * HOUR);
+        assert_eq!(time.to_string(), "2026-10-17T09:30:00+02:00");
+        assert_eq!(
+            "2026-10-17T23:00-01:00"
+                .parse::<DateTime>()
+                .unwrap()
+                .to_string(),
+            "2026-10-17T23:00:00-01:00"
+        );
+        assert_eq!(
+            time.format("%A %d %B %Y, %H:%M %z").unwrap(),
+            "Saturday 17 October 2026, 09:30 +0200"
+        );
+        assert_eq!(date.format("%j %y %b %a").unwrap(), "290 26 Oct Sat");
+        assert!(date.format("%q").is_err());
+
+        assert!("2026-02-29".parse::<DateTime>().is_err());
+        assert!("2024-02-29".parse::<DateTime>().is_ok());
+        assert!("2026-13-01".parse::<DateTime>().is_err());
+        assert!("2026-10-17T25:00".parse::<DateTime>().is_err());
+        assert_eq!(parse_offset("-05:30"), Some(-19800));
+        assert_eq!(parse_offset("UTC"), Some(0));
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(parse_duration("PT3H30M"), Ok(3.5 * HOUR));
+        assert_eq!(parse_duration("P1W2DT1.5S"), Ok(9.0 * DAY + 1.5));
+        assert_eq!(parse_duration("-P1D"), Ok(-DAY));
+        assert!(parse_duration("P1Y").is_err());
+        assert!(parse_duration("PT").is_err());
+        assert!(parse_duration("P1H").is_err());
+        assert!(parse_duration("P999999999999W").is_err());
+        assert_eq!(format_duration(3.5 * HOUR), "PT3H30M");
+        assert_eq!(format_duration(DAY + 2.5), "P1DT2.5S");
+        assert_eq!(format_duration(-2.0 * DAY), "-P2D");
+        assert_eq!(format_duration(0.0), "PT0S");
+
+        assert_eq!(literal_len("2026-10-17-PT1H"), Some(10));
+        assert_eq!(literal_len("PT1H)"), Some(4));
+        assert_eq!(literal_len("PT1Hours"), None);
+        assert_eq!(literal_len("Pa"), None);
+    }
+}
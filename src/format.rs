@@ -0,0 +1,417 @@
+//! Ways of showing values other than their `Display`, like in hexadecimal or
+//! in a fixed word size for programmer use.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::math::{MathOperator, Value};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hex,
+    Octal,
+    Binary,
+}
+
+impl FromStr for Radix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dec" => Ok(Radix::Decimal),
+            "hex" => Ok(Radix::Hex),
+            "oct" => Ok(Radix::Octal),
+            "bin" => Ok(Radix::Binary),
+            _ => Err(format!("Invalid Radix: {}", s)),
+        }
+    }
+}
+
+impl Radix {
+    /// Formats a non-negative integer with the radix's prefix, e.g. `0xFF`
+    fn format(self, n: u128) -> String {
+        match self {
+            Radix::Decimal => n.to_string(),
+            Radix::Hex => format!("0x{:X}", n),
+            Radix::Octal => format!("0o{:o}", n),
+            Radix::Binary => format!("0b{:b}", n),
+        }
+    }
+}
+
+/// The size of the integers of a programmer's calculator, which results wrap
+/// around to fit in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WordSize {
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl FromStr for WordSize {
+    type Err = String;
+
+    /// Parses sizes like `i16` or `u8`, or just `16` for signed integers
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (signed, bits) = match s.as_bytes().first() {
+            Some(b'i') => (true, &s[1..]),
+            Some(b'u') => (false, &s[1..]),
+            _ => (true, s),
+        };
+        match bits.parse() {
+            Ok(bits @ (8 | 16 | 32 | 64)) => Ok(WordSize { bits, signed }),
+            _ => Err(format!("Invalid Word Size: {}", s)),
+        }
+    }
+}
+
+impl Display for WordSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", if self.signed { 'i' } else { 'u' }, self.bits)
+    }
+}
+
+impl WordSize {
+    /// The bits of an integer in the word, as an unsigned integer
+    fn bits_of(self, n: i128) -> u128 {
+        n.rem_euclid(1 << self.bits) as u128
+    }
+
+    /// Wraps an integer around to fit in the word
+    pub fn wrap(self, n: i128) -> i128 {
+        let bits = self.bits_of(n);
+        if self.signed && bits >> (self.bits - 1) == 1 {
+            bits as i128 - (1 << self.bits)
+        } else {
+            bits as i128
+        }
+    }
+
+    /// Truncates the numbers in a value to exact integers wrapped around to fit
+    /// in the word, leaving infinities, NaNs and anything else as they are.
+    /// Numbers too big for an `i128` are out of range.
+    pub fn wrap_value(self, value: Value) -> Result<Value, String> {
+        Ok(match value {
+            Value::Number(num) if num.is_finite() => {
+                let num = num.trunc();
+                if num.abs() >= i128::MAX as f64 {
+                    return Err(format!("out of range: {}", self));
+                }
+                Value::Integer(self.wrap(num as i128))
+            }
+            Value::Integer(n) => Value::Integer(self.wrap(n)),
+            Value::List(list) => Value::List(
+                list.into_iter()
+                    .map(|x| self.wrap_value(x))
+                    .collect::<Result<_, _>>()?,
+            ),
+            value => value,
+        })
+    }
+
+    /// Runs `popcount`, `clz` or `ctz` on the bits of the word
+    pub fn count_bits(self, op: MathOperator, x: Value) -> Result<Value, String> {
+        let bits = match self.wrap_value(x)? {
+            Value::Integer(n) => self.bits_of(n),
+            Value::List(list) => {
+                return list
+                    .into_iter()
+                    .map(|x| self.count_bits(op, x))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List)
+            }
+            _ => return Err(format!("invalid type: {}", op)),
+        };
+        Ok(Value::Integer(match op {
+            MathOperator::Popcount => bits.count_ones(),
+            MathOperator::Clz => bits.leading_zeros() - (128 - self.bits),
+            _ => bits.trailing_zeros().min(self.bits),
+        } as i128))
+    }
+}
+
+/// How numbers that aren't shown in another radix are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// The shortest form that reads back as the same number, or with
+    /// significant digits, like `%g` in C
+    #[default]
+    Auto,
+    /// A fixed number of decimals, e.g. `3.14`
+    Fixed(usize),
+    /// One digit before the point, e.g. `1.5e3`
+    Scientific,
+    /// An exponent that is a multiple of 3, e.g. `15e3`
+    Engineering,
+}
+
+/// How the binaries show results
+#[derive(Clone, Debug, Default)]
+pub struct Format {
+    pub radix: Radix,
+    /// Shows negative integers in other radixes as their bits in a word, e.g.
+    /// `-1` as `0xFF` in 8 bits, instead of `-0x1`
+    pub word_size: Option<WordSize>,
+    pub notation: Notation,
+    /// The significant digits of the auto, scientific and engineering
+    /// notations, rather than as many as needed
+    pub digits: Option<usize>,
+    /// Groups the digits before the point in threes, e.g. `1,234,567`
+    pub thousands: bool,
+    /// Writes the point as a comma, and groups thousands with `.`
+    pub decimal_comma: bool,
+}
+
+impl Format {
+    pub fn format(&self, value: &Value) -> String {
+        let mut s = String::new();
+        let commas = self.thousands || self.decimal_comma;
+        value
+            .write(
+                &mut s,
+                &|num| self.number(num),
+                &|n| self.integer(n),
+                commas,
+            )
+            .unwrap();
+        s
+    }
+
+    /// Formats an exact integer, in the radix if there's one
+    fn integer(&self, n: i128) -> String {
+        match (self.radix, self.word_size) {
+            (Radix::Decimal, _) if self.notation == Notation::Auto && self.digits.is_none() => {
+                self.separators(&n.to_string())
+            }
+            (Radix::Decimal, _) => self.number(n as f64),
+            (radix, Some(word)) => radix.format(word.bits_of(n)),
+            (radix, None) if n < 0 => format!("-{}", radix.format(n.unsigned_abs())),
+            (radix, None) => radix.format(n as u128),
+        }
+    }
+
+    fn number(&self, num: f64) -> String {
+        // bigger integers are written in decimal, since they'd saturate
+        if self.radix != Radix::Decimal && num.fract() == 0.0 && num.abs() < i128::MAX as f64 {
+            return self.integer(num as i128);
+        }
+        if !num.is_finite() {
+            return num.to_string();
+        }
+        let s = match (self.notation, self.digits) {
+            (Notation::Auto, None) => num.to_string(),
+            (Notation::Auto, Some(digits)) => general(num, digits.max(1)),
+            (Notation::Fixed(decimals), _) => format!("{:.*}", decimals, num),
+            (Notation::Scientific, None) => format!("{:e}", num),
+            (Notation::Scientific, Some(digits)) => format!("{:.*e}", digits.max(1) - 1, num),
+            (Notation::Engineering, None) => engineering(&format!("{:e}", num)),
+            (Notation::Engineering, Some(digits)) => {
+                engineering(&format!("{:.*e}", digits.max(1) - 1, num))
+            }
+        };
+        self.separators(&s)
+    }
+
+    /// Adds the thousands separators and decimal comma to a formatted number
+    fn separators(&self, s: &str) -> String {
+        let (sign, s) = match s.strip_prefix('-') {
+            Some(s) => ("-", s),
+            None => ("", s),
+        };
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (int, rest) = s.split_at(end);
+        let (point, group) = match self.decimal_comma {
+            true => (",", '.'),
+            false => (".", ','),
+        };
+        let mut out = sign.to_string();
+        for (i, c) in int.chars().enumerate() {
+            if self.thousands && i > 0 && (int.len() - i) % 3 == 0 {
+                out.push(group);
+            }
+            out.push(c);
+        }
+        out + &rest.replacen('.', point, 1)
+    }
+}
+
+/// Splits a number formatted with `{:e}` into its mantissa and exponent
+fn split_exponent(s: &str) -> (&str, i32) {
+    let (mantissa, exp) = s.split_once('e').unwrap();
+    (mantissa, exp.parse().unwrap())
+}
+
+/// Formats a number with significant digits, in scientific notation if it's
+/// very large or small, without trailing zeros
+fn general(num: f64, digits: usize) -> String {
+    let sci = format!("{:.*e}", digits - 1, num);
+    let (mantissa, exp) = split_exponent(&sci);
+    if exp < -4 || exp >= digits as i32 {
+        format!("{}e{}", trim_zeros(mantissa), exp)
+    } else {
+        let decimals = (digits as i32 - 1 - exp) as usize;
+        trim_zeros(&format!("{:.*}", decimals, num)).to_string()
+    }
+}
+
+fn trim_zeros(s: &str) -> &str {
+    match s.contains('.') {
+        true => s.trim_end_matches('0').trim_end_matches('.'),
+        false => s,
+    }
+}
+
+/// Moves the point of a number formatted with `{:e}` so the exponent is a
+/// multiple of 3
+fn engineering(sci: &str) -> String {
+    let (mantissa, exp) = split_exponent(sci);
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => ("-", m),
+        None => ("", mantissa),
+    };
+    let shift = exp.rem_euclid(3) as usize;
+    let mut digits = mantissa.replace('.', "");
+    while digits.len() < shift + 1 {
+        digits.push('0');
+    }
+    let (int, frac) = digits.split_at(shift + 1);
+    match frac {
+        "" => format!("{}{}e{}", sign, int, exp - shift as i32),
+        _ => format!("{}{}.{}e{}", sign, int, frac, exp - shift as i32),
+    }
+}
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+    use crate::matrix::Matrix;
+
+    #[test]
+    fn test_word_size() {
+        let u8: WordSize = "u8".parse().unwrap();
+        let i8: WordSize = "8".parse().unwrap();
+        assert_eq!(i8, "i8".parse().unwrap());
+        assert_eq!(u8.wrap(256), 0);
+        assert_eq!(u8.wrap(-1), 255);
+        assert_eq!(i8.wrap(128), -128);
+        assert_eq!(i8.wrap(-129), 127);
+        assert_eq!(u8.wrap_value(Value::Number(3.9)), Ok(Value::Integer(3)));
+        assert_eq!(
+            u8.wrap_value(Value::Number(f64::INFINITY)),
+            Ok(Value::Number(f64::INFINITY))
+        );
+        assert_eq!(
+            u8.wrap_value(Value::Number(1e300)),
+            Err("out of range: u8".to_string())
+        );
+        let u32: WordSize = "u32".parse().unwrap();
+        let i32: WordSize = "i32".parse().unwrap();
+        assert_eq!(u32.wrap(-1), 4294967295);
+        assert_eq!(i32.wrap(2147483648), -2147483648);
+        let u64: WordSize = "u64".parse().unwrap();
+        let i64: WordSize = "64".parse().unwrap();
+        assert_eq!(i64, "i64".parse().unwrap());
+        assert_eq!(u64.wrap(-1), u64::MAX as i128);
+        assert_eq!(u64.wrap(u64::MAX as i128 + 1), 0);
+        assert_eq!(i64.wrap(i64::MAX as i128), i64::MAX as i128);
+        assert_eq!(i64.wrap(i64::MAX as i128 + 1), i64::MIN as i128);
+        assert_eq!(
+            u64.count_bits(MathOperator::Clz, Value::Integer(1)),
+            Ok(Value::Integer(63))
+        );
+        assert_eq!(
+            u8.count_bits(MathOperator::Ctz, Value::Integer(0)),
+            Ok(Value::Integer(8))
+        );
+        assert_eq!(u8.to_string(), "u8");
+        assert!("12".parse::<WordSize>().is_err());
+        assert!("x8".parse::<WordSize>().is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let mut format = Format {
+            radix: Radix::Hex,
+            ..Default::default()
+        };
+        assert_eq!(format.format(&Value::Number(255.0)), "0xFF");
+        assert_eq!(format.format(&Value::Number(-16.0)), "-0x10");
+        assert_eq!(format.format(&Value::Number(1.5)), "1.5");
+        format.word_size = Some("i16".parse().unwrap());
+        assert_eq!(format.format(&Value::Number(-1.0)), "0xFFFF");
+        format.word_size = Some("u32".parse().unwrap());
+        assert_eq!(format.format(&Value::Number(-1.0)), "0xFFFFFFFF");
+        format.word_size = Some("u64".parse().unwrap());
+        assert_eq!(format.format(&Value::Integer(-1)), "0xFFFFFFFFFFFFFFFF");
+        format.word_size = None;
+        // too big for an integer, so it's in decimal
+        assert_eq!(format.format(&Value::Number(1e300)), 1e300.to_string());
+        format.word_size = Some("i16".parse().unwrap());
+        format.radix = Radix::Binary;
+        assert_eq!(
+            format.format(&Value::List(vec![
+                Value::Number(5.0),
+                Value::String("a".into())
+            ])),
+            "[0b101, \"a\"]"
+        );
+        format.radix = Radix::Octal;
+        assert_eq!(format.format(&Value::Number(8.0)), "0o10");
+        format.radix = Radix::Decimal;
+        assert_eq!(format.format(&Value::Number(-1.0)), "-1");
+    }
+
+    #[test]
+    fn test_notation() {
+        let format = |format: &Format, x: f64| format.format(&Value::Number(x));
+        let mut f = Format {
+            digits: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(format(&f, 1.23456), "1.23");
+        assert_eq!(format(&f, 2.0), "2");
+        assert_eq!(format(&f, 123456.0), "1.23e5");
+        assert_eq!(format(&f, 0.000012345), "1.23e-5");
+        assert_eq!(format(&f, 0.0), "0");
+        assert_eq!(format(&f, f64::INFINITY), "inf");
+        f.notation = Notation::Scientific;
+        assert_eq!(format(&f, 1500.0), "1.50e3");
+        f.notation = Notation::Engineering;
+        assert_eq!(format(&f, 12345.0), "12.3e3");
+        assert_eq!(format(&f, -0.00012), "-120e-6");
+        f.digits = None;
+        assert_eq!(format(&f, 100000.0), "100e3");
+        assert_eq!(format(&f, 1.5), "1.5e0");
+        f.notation = Notation::Fixed(2);
+        assert_eq!(format(&f, 2.0 / 3.0), "0.67");
+        f.thousands = true;
+        assert_eq!(format(&f, -1234567.891), "-1,234,567.89");
+        f.decimal_comma = true;
+        assert_eq!(format(&f, 1234.5), "1.234,50");
+        f.notation = Notation::Auto;
+        assert_eq!(format(&f, 999.0), "999");
+        assert_eq!(
+            f.format(&Value::Interval(1000.0, 2000.5)),
+            "[1.000 .. 2.000,5]"
+        );
+    }
+
+    #[test]
+    fn test_separators() {
+        let list = Value::List(vec![Value::Number(1.5), Value::Number(1234.0)]);
+        let matrix = Matrix::from_rows(vec![vec![1.5, 2.0], vec![3.0, 4.0]]).unwrap();
+        let matrix = Value::Matrix(matrix);
+        let mut f = Format::default();
+        assert_eq!(f.format(&list), "[1.5, 1234]");
+        assert_eq!(f.format(&matrix), "[1.5, 2; 3, 4]");
+        // the commas in the numbers would be mistaken for separators
+        f.thousands = true;
+        assert_eq!(f.format(&list), "[1.5; 1,234]");
+        f.thousands = false;
+        f.decimal_comma = true;
+        assert_eq!(f.format(&list), "[1,5; 1234]");
+        assert_eq!(f.format(&matrix), "[1,5 2; 3 4]");
+        let nested = Value::List(vec![list, Value::String("a, b".into())]);
+        assert_eq!(f.format(&nested), "[[1,5; 1234]; \"a, b\"]");
+    }
+}
@@ -1,18 +1,60 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use crate::{
-    math::{Associativity, MathOperator},
-    rpn::RpnItem,
-    utils::normalize_whitespace,
+    datetime::{self, DateTime},
+    interval,
+    math::{self, Associativity, MathOperator, FUNCTION_PRECEDENCE},
+    rpn::{Builtin, Interpreter, RpnItem, Word},
+    units::Unit,
+    utils::{
+        normalize_whitespace_with_offsets, offset_in, parse_big_integer, parse_number,
+        parse_string_literal, split_tokens, LineError,
+    },
 };
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum InfixItem {
     Operand(f64),
+    /// An integer too big to be exact as a float, like `0xFFFFFFFFFFFFFFFF`
+    Integer(i128),
     Boolean(bool),
+    String(String),
+    DateTime(DateTime),
+    /// A duration in seconds
+    Duration(f64),
     Operator(MathOperator),
+    Builtin(Builtin),
+    /// A local variable or a unit, e.g. the `km` in `5 km`
+    Ident(String),
+    /// Conversion of everything before it to the unit after it (`in km/h`)
+    In,
     OpenParen,
     CloseParen,
+    OpenBracket,
+    CloseBracket,
+    OpenBrace,
+    CloseBrace,
+    /// Separates the arguments of functions like `substr(s, 1, 3)` and the
+    /// elements of lists
+    Comma,
+    /// Separates the rows of matrices like `[1, 2; 3, 4]`
+    Semicolon,
+    /// Separates the parameters of a block from its body, e.g. `{x -> x * 2}`
+    Arrow,
+    /// Starts the branches of a conditional like `x > 0 ? x : -x`
+    Question,
+    /// Separates the branches of a conditional like `x > 0 ? x : -x`
+    Colon,
+    /// The keywords of a conditional like `if x > 0 then x else -x`
+    If,
+    Then,
+    Else,
+    /// Starts local variables like `let r = 5, h = 2 in pi r^2 h`, whose
+    /// bindings end at the `in`
+    Let,
+    /// A call of a user-defined function like `f(3, 4)`, which the compiler
+    /// makes of the function's name
+    Call(String),
 }
 
 impl FromStr for InfixItem {
@@ -22,10 +64,32 @@ impl FromStr for InfixItem {
         match s {
             "(" => return Ok(InfixItem::OpenParen),
             ")" => return Ok(InfixItem::CloseParen),
+            "[" => return Ok(InfixItem::OpenBracket),
+            "]" => return Ok(InfixItem::CloseBracket),
+            "{" => return Ok(InfixItem::OpenBrace),
+            "}" => return Ok(InfixItem::CloseBrace),
+            "," => return Ok(InfixItem::Comma),
+            ";" => return Ok(InfixItem::Semicolon),
+            "->" => return Ok(InfixItem::Arrow),
+            "?" => return Ok(InfixItem::Question),
+            ":" => return Ok(InfixItem::Colon),
+            "if" => return Ok(InfixItem::If),
+            "then" => return Ok(InfixItem::Then),
+            "else" => return Ok(InfixItem::Else),
+            "let" => return Ok(InfixItem::Let),
+            "in" | "to" => return Ok(InfixItem::In),
             _ => {}
         }
 
-        if let Ok(num) = s.parse::<f64>() {
+        if let Some(string) = parse_string_literal(s) {
+            return string.map(InfixItem::String);
+        }
+
+        if let Some(n) = parse_big_integer(s) {
+            return Ok(InfixItem::Integer(n));
+        }
+
+        if let Some(num) = parse_number(s) {
             return Ok(InfixItem::Operand(num));
         }
 
@@ -33,107 +97,1485 @@ impl FromStr for InfixItem {
             return Ok(InfixItem::Boolean(b));
         }
 
+        if let Ok(datetime) = s.parse::<DateTime>() {
+            return Ok(InfixItem::DateTime(datetime));
+        }
+
+        if let Ok(seconds) = datetime::parse_duration(s) {
+            return Ok(InfixItem::Duration(seconds));
+        }
+
         if let Ok(op) = s.parse::<MathOperator>() {
             return Ok(InfixItem::Operator(op));
         }
 
+        if let Some(builtin) = s.parse::<Builtin>().ok().filter(|b| !b.is_rpn_only()) {
+            return Ok(InfixItem::Builtin(builtin));
+        }
+
+        let mut chars = s.chars();
+        if chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_')
+        {
+            return Ok(InfixItem::Ident(s.to_string()));
+        }
+
         match s.chars().nth(0) {
-            Some(c) if c.is_digit(10) => Err(format!("Invalid Number: {}", s).into()),
-            _ => Err(format!("Invalid Operator: {}", s).into()),
+            Some(c) if c.is_ascii_digit() || c == '.' => Err(format!("Invalid Number: {}", s)),
+            _ => Err(format!("Invalid Operator: {}", s)),
         }
     }
 }
 
 impl InfixItem {
-    fn to_rpn_item(&self) -> Result<RpnItem, bool> {
+    /// Precedence and associativity of operators and functions
+    fn precedence(&self) -> Option<(u8, Associativity)> {
+        match *self {
+            InfixItem::Operator(op) => Some((op.precedence(), op.associativity())),
+            // builtins are functions like `sqrt`
+            InfixItem::Builtin(_) | InfixItem::Call(_) => {
+                Some((FUNCTION_PRECEDENCE, Associativity::Right))
+            }
+            _ => None,
+        }
+    }
+
+    fn to_rpn_item(&self) -> Option<RpnItem> {
         match *self {
-            InfixItem::Operand(num) => Ok(RpnItem::Operand(num)),
-            InfixItem::Boolean(b) => Ok(RpnItem::Boolean(b)),
-            InfixItem::Operator(op) => Ok(RpnItem::Operator(op)),
-            InfixItem::OpenParen => Err(true),
-            InfixItem::CloseParen => Err(false),
+            InfixItem::Operator(op) => Some(RpnItem::Operator(op)),
+            InfixItem::Builtin(builtin) => Some(RpnItem::Builtin(builtin)),
+            InfixItem::Call(ref name) => Some(RpnItem::Call(name.clone())),
+            _ => None,
+        }
+    }
+
+    /// Whether the item can be the last item of an operand
+    fn ends_operand(&self) -> bool {
+        matches!(
+            self,
+            InfixItem::Operand(_)
+                | InfixItem::Integer(_)
+                | InfixItem::Boolean(_)
+                | InfixItem::String(_)
+                | InfixItem::DateTime(_)
+                | InfixItem::Duration(_)
+                | InfixItem::Ident(_)
+                | InfixItem::CloseParen
+                | InfixItem::CloseBracket
+                | InfixItem::CloseBrace
+        ) || matches!(self, InfixItem::Operator(op) if op.is_postfix())
+    }
+}
+
+/// Replaces `!` after an operand, as in `3!`, with the factorial, since before
+/// an operand it's logical not
+fn fix_factorials(vec: &mut [InfixItem]) {
+    for i in 1..vec.len() {
+        if vec[i] == InfixItem::Operator(MathOperator::Not) && vec[i - 1].ends_operand() {
+            vec[i] = InfixItem::Operator(MathOperator::Factorial);
         }
     }
 }
 
-/// Replaces `<not a number> - <number>` with `<not a number> <negative number>`
-fn fix_negative_numbers(vec: &mut Vec<InfixItem>) {
+/// Replaces `<not an operand> - <number>` with `<not an operand> <negative number>`,
+/// and likewise for durations, keeping `offsets` in step. A number with postfix
+/// operators is subtracted from 0 after them instead, so `-3!` is `-(3!)`.
+fn fix_negative_numbers(vec: &mut Vec<InfixItem>, offsets: &mut Vec<usize>) {
     let mut i = 0;
-    while i < vec.len() - 1 {
+    while i + 1 < vec.len() {
         if let InfixItem::Operator(MathOperator::Sub) = vec[i] {
-            if i > 0 && matches!(vec[i - 1], InfixItem::Operand(_)) {
+            if i > 0 && vec[i - 1].ends_operand() {
                 i += 1;
                 continue;
             }
-            if let InfixItem::Operand(num) = vec[i + 1] {
-                vec[i] = InfixItem::Operand(-num);
-                vec.remove(i + 1);
+            let postfix = vec[i + 2..]
+                .iter()
+                .take_while(|item| matches!(item, InfixItem::Operator(op) if op.is_postfix()))
+                .count();
+            match vec[i + 1] {
+                InfixItem::Operand(_) | InfixItem::Integer(_) if postfix > 0 => {
+                    let end = i + 2 + postfix;
+                    vec.insert(end, InfixItem::CloseParen);
+                    offsets.insert(end, offsets[end - 1]);
+                    vec.splice(i..i, [InfixItem::OpenParen, InfixItem::Operand(0.0)]);
+                    offsets.splice(i..i, [offsets[i]; 2]);
+                    i = end + 3;
+                    continue;
+                }
+                InfixItem::Operand(num) => vec[i] = InfixItem::Operand(-num),
+                InfixItem::Integer(n) => vec[i] = InfixItem::Integer(-n),
+                InfixItem::Duration(seconds) => vec[i] = InfixItem::Duration(-seconds),
+                _ => {
+                    i += 1;
+                    continue;
+                }
             }
+            vec.remove(i + 1);
+            offsets.remove(i + 1);
         }
         i += 1;
     }
 }
 
 pub fn parse_infix(s: &str) -> Result<Vec<InfixItem>, String> {
-    let mut vec = normalize_whitespace(s)
-        .split_whitespace()
-        .map(|token| token.parse())
-        .collect::<Result<_, _>>()?;
-    fix_negative_numbers(&mut vec);
-    Ok(vec)
+    parse_infix_with_offsets(s)
+        .map(|(items, _)| items)
+        .map_err(|e| e.message)
+}
+
+/// Like [`parse_infix`], also returning the byte offset in `s` of each item,
+/// and reporting errors at the token they're about
+pub fn parse_infix_with_offsets(s: &str) -> Result<(Vec<InfixItem>, Vec<usize>), LineError> {
+    let (normalized, normalized_offsets) = normalize_whitespace_with_offsets(s);
+    let mut vec = Vec::new();
+    let mut offsets = Vec::new();
+    for token in split_tokens(&normalized) {
+        let offset = normalized_offsets[offset_in(&normalized, token)];
+        vec.push(
+            token
+                .parse()
+                .map_err(|message| LineError { message, offset })?,
+        );
+        offsets.push(offset);
+    }
+    fix_factorials(&mut vec);
+    fix_negative_numbers(&mut vec, &mut offsets);
+    Ok((vec, offsets))
+}
+
+/// Functions like `hypot` and `clamp` written in infix, which the binary
+/// defines at startup
+pub const PRELUDE: &str = include_str!("prelude.inf");
+
+/// The file imported by a line like `import "stats.inf"`, if it's an import
+pub fn parse_import(s: &str) -> Option<String> {
+    match parse_infix(s).ok()?.as_slice() {
+        [InfixItem::Ident(import), InfixItem::String(path)] if import == "import" => {
+            Some(path.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Whether `s` goes on on the next line, because it has unclosed parentheses,
+/// brackets or braces, or ends with an operator or a keyword
+pub fn is_incomplete(s: &str) -> bool {
+    let Ok(tokens) = parse_infix(s) else {
+        return false;
+    };
+    let depth = tokens.iter().fold(0, |depth, token| match token {
+        InfixItem::OpenParen | InfixItem::OpenBracket | InfixItem::OpenBrace => depth + 1,
+        InfixItem::CloseParen | InfixItem::CloseBracket | InfixItem::CloseBrace => depth - 1,
+        _ => depth,
+    });
+    depth > 0
+        || matches!(
+            tokens.last(),
+            Some(
+                InfixItem::Builtin(_)
+                    | InfixItem::Comma
+                    | InfixItem::Semicolon
+                    | InfixItem::Arrow
+                    | InfixItem::Question
+                    | InfixItem::Colon
+                    | InfixItem::If
+                    | InfixItem::Then
+                    | InfixItem::Else
+                    | InfixItem::Let
+                    | InfixItem::In
+            )
+        )
+        || matches!(tokens.last(), Some(InfixItem::Operator(op)) if !op.is_postfix())
 }
 
 pub fn infix_to_rpn(tokens: &[InfixItem]) -> Result<Vec<RpnItem>, String> {
-    let mut stack = Vec::new();
-    let mut output = Vec::new();
+    Compiler::default().compile(tokens).map(|code| code.items)
+}
+
+/// Converts infix to RPN that can call the functions defined in
+/// `interpreter`. If `strict`, juxtaposition like `2x` is an error instead of
+/// multiplication. `offsets` are the byte offsets of `tokens` in their line,
+/// which errors are reported at, and the offsets of the tokens the RPN items
+/// come from are returned with them.
+pub fn infix_to_rpn_with(
+    tokens: &[InfixItem],
+    offsets: &[usize],
+    interpreter: &Interpreter,
+    strict: bool,
+) -> Result<(Vec<RpnItem>, Vec<usize>), LineError> {
+    let mut compiler = Compiler::new(interpreter, strict);
+    let code = compiler
+        .compile(tokens)
+        .map_err(|message| compiler.error(message, tokens, offsets))?;
+    let sources = code.sources.iter();
+    let offsets = sources
+        .map(|source| offsets[index_of(tokens, source)])
+        .collect();
+    Ok((code.items, offsets))
+}
 
-    for token in tokens {
-        match *token {
-            InfixItem::Operand(num) => output.push(RpnItem::Operand(num)),
-            InfixItem::Boolean(b) => output.push(RpnItem::Boolean(b)),
+/// Defines a function in `interpreter` if `tokens` are a definition like
+/// `f(x, y) = x^2 + y`, compiling its body to RPN once. `source` is shown when
+/// listing definitions, and errors are reported at the `offsets` of `tokens`.
+pub fn define_function(
+    tokens: &[InfixItem],
+    offsets: &[usize],
+    source: &str,
+    interpreter: &Interpreter,
+    strict: bool,
+) -> Option<Result<(), LineError>> {
+    let (name, rest) = match tokens {
+        [InfixItem::Ident(name), InfixItem::OpenParen, rest @ ..] => (name.clone(), rest),
+        // functions like `len` and `map`, which can't be redefined
+        [InfixItem::Operator(op), InfixItem::OpenParen, rest @ ..]
+            if op.to_string().starts_with(|c: char| c.is_alphabetic()) =>
+        {
+            (op.to_string(), rest)
+        }
+        [InfixItem::Builtin(builtin), InfixItem::OpenParen, rest @ ..] => {
+            (builtin.to_string(), rest)
+        }
+        _ => return None,
+    };
+    let close = rest.iter().position(|t| *t == InfixItem::CloseParen)?;
+    if rest.get(close + 1) != Some(&InfixItem::Operator(MathOperator::Eq)) {
+        return None;
+    }
+    // otherwise it's a comparison like `f(2) = 4`
+    let params = &rest[..close];
+    if !params.iter().enumerate().all(|(i, param)| match param {
+        InfixItem::Ident(_) => i % 2 == 0,
+        InfixItem::Comma => i % 2 == 1,
+        _ => false,
+    }) || params.last() == Some(&InfixItem::Comma)
+    {
+        return None;
+    }
+    if !matches!(tokens[0], InfixItem::Ident(_)) {
+        // `sqrt(pi) = 1` is still a comparison
+        if params
+            .iter()
+            .any(|param| matches!(param, InfixItem::Ident(name) if math::constant(name).is_some()))
+        {
+            return None;
+        }
+        return Some(Err(LineError {
+            message: format!("Cannot Redefine Builtin: {}", name),
+            offset: offsets[0],
+        }));
+    }
+    let body = &rest[close + 2..];
+    if body.is_empty() {
+        return Some(Err(LineError {
+            message: "Missing Body after =".into(),
+            // the `=`, after the name and the `(`
+            offset: offsets[close + 3],
+        }));
+    }
+
+    let arity = params.len().div_ceil(2);
+    let mut compiler = Compiler::new(interpreter, strict);
+    // so it can call itself
+    compiler.functions.insert(name.clone(), Some(arity));
+    let body = match compiler.compile_function(params, body) {
+        Ok(body) => body,
+        Err(message) => return Some(Err(compiler.error(message, tokens, offsets))),
+    };
+    let word = Word {
+        body,
+        arity: Some(arity),
+        source: source.trim().into(),
+    };
+    interpreter.define(&name, word);
+    Some(Ok(()))
+}
+
+/// The index in `tokens` of `token`, which is one of them
+fn index_of(tokens: &[InfixItem], token: &InfixItem) -> usize {
+    let offset = token as *const InfixItem as usize - tokens.as_ptr() as usize;
+    offset / std::mem::size_of::<InfixItem>()
+}
+
+/// RPN compiled from infix, with the infix token each item comes from, which
+/// errors running it are reported at
+#[derive(Default)]
+struct Code<'a> {
+    items: Vec<RpnItem>,
+    sources: Vec<&'a InfixItem>,
+}
+
+impl<'a> Code<'a> {
+    fn push(&mut self, item: RpnItem, source: &'a InfixItem) {
+        self.items.push(item);
+        self.sources.push(source);
+    }
+
+    fn extend(&mut self, code: Code<'a>) {
+        self.items.extend(code.items);
+        self.sources.extend(code.sources);
+    }
+
+    /// Adds what was popped from the operator stack, if it's an operator or a
+    /// function
+    fn push_pending(&mut self, (item, source): (InfixItem, &'a InfixItem)) {
+        if let Some(item) = item.to_rpn_item() {
+            self.push(item, source);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Compiler<'a> {
+    /// The local variables in scope and their slots, innermost last
+    scope: Vec<(String, usize)>,
+    /// Number of local variable slots used so far
+    slots: usize,
+    /// Disables implicit multiplication
+    strict: bool,
+    /// The user-defined functions that can be called, and their number of
+    /// parameters if it's known
+    functions: HashMap<String, Option<usize>>,
+    /// The token being compiled, which errors are reported at
+    at: Option<&'a InfixItem>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(interpreter: &Interpreter, strict: bool) -> Compiler<'a> {
+        let functions = interpreter
+            .words
+            .borrow()
+            .iter()
+            .map(|(name, word)| (name.clone(), word.arity))
+            .collect();
+        Compiler {
+            strict,
+            functions,
+            ..Default::default()
+        }
+    }
+
+    /// Reports an error at the token the compiler was at, where `offsets` are
+    /// the offsets of the `tokens` it was given
+    fn error(&self, message: String, tokens: &[InfixItem], offsets: &[usize]) -> LineError {
+        let offset = self.at.map_or(0, |at| offsets[index_of(tokens, at)]);
+        LineError { message, offset }
+    }
+
+    /// Whether `tokens[i]` is a call of a user-defined function, which local
+    /// variables shadow
+    fn is_call(&self, tokens: &[InfixItem], i: usize) -> bool {
+        match (&tokens[i], tokens.get(i + 1)) {
+            (InfixItem::Ident(name), Some(InfixItem::OpenParen)) => {
+                self.local(name).is_none() && self.functions.contains_key(name)
+            }
+            _ => false,
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<usize> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, slot)| *slot)
+    }
+
+    /// Whether an item can start an operand that implicitly multiplies the
+    /// one before it, as in `2pi`, `3(4+5)` or `2sqrt(x)`
+    fn starts_factor(&self, tokens: &[InfixItem], i: usize) -> bool {
+        if self.is_call(tokens, i) {
+            return true;
+        }
+        match &tokens[i] {
+            InfixItem::Operand(_) | InfixItem::Integer(_) | InfixItem::OpenParen => true,
+            InfixItem::Ident(name) => self.local(name).is_some() || math::constant(name).is_some(),
+            // functions like `sqrt`, but not operators like `^`, `and` or `!`
             InfixItem::Operator(op) => {
-                while let Some(top) = stack.last() {
-                    if let InfixItem::Operator(top_op) = top {
-                        if (op.associativity() == Associativity::Left
-                            && op.precedence() <= top_op.precedence())
-                            || (op.associativity() == Associativity::Right
-                                && op.precedence() < top_op.precedence())
-                        {
-                            output.push(RpnItem::Operator(*top_op));
-                            stack.pop();
-                        } else {
-                            break;
+                op.precedence() == FUNCTION_PRECEDENCE
+                    && !op.is_postfix()
+                    && op.to_string().starts_with(|c: char| c.is_alphabetic())
+            }
+            _ => false,
+        }
+    }
+
+    /// Pushes an operator to the operator stack, first moving the operators
+    /// that bind at least as tightly to the output
+    fn push_operator(
+        op: MathOperator,
+        source: &'a InfixItem,
+        stack: &mut Vec<(InfixItem, &'a InfixItem)>,
+        output: &mut Code<'a>,
+    ) {
+        Self::pop_tighter(op, stack, output);
+        stack.push((InfixItem::Operator(op), source));
+    }
+
+    /// Moves the operators on the stack that bind at least as tightly as `op`
+    /// to the output, completing its left operand
+    fn pop_tighter(
+        op: MathOperator,
+        stack: &mut Vec<(InfixItem, &'a InfixItem)>,
+        output: &mut Code<'a>,
+    ) {
+        while let Some((top_precedence, _)) = stack.last().and_then(|(top, _)| top.precedence()) {
+            if (op.associativity() == Associativity::Left && op.precedence() <= top_precedence)
+                || (op.associativity() == Associativity::Right && op.precedence() < top_precedence)
+            {
+                output.push_pending(stack.pop().unwrap());
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Moves the operators on the stack to the output, down to the innermost
+    /// open parenthesis or bracket, for things that bind looser than them
+    fn pop_operators(stack: &mut Vec<(InfixItem, &'a InfixItem)>, output: &mut Code<'a>) {
+        while stack
+            .last()
+            .is_some_and(|(top, _)| top.to_rpn_item().is_some())
+        {
+            output.push_pending(stack.pop().unwrap());
+        }
+    }
+
+    /// Converts infix to RPN with the shunting-yard algorithm
+    fn compile(&mut self, tokens: &'a [InfixItem]) -> Result<Code<'a>, String> {
+        let mut stack = Vec::new();
+        let mut output = Code::default();
+        // whether the next item is the start of an operand, as opposed to an
+        // operator or a unit applying to the operand before it
+        let mut expect_operand = true;
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            self.at = Some(token);
+            i += 1;
+            // juxtaposition is multiplication, as in `2pi` or `(a+b)(a-b)`,
+            // except of two numbers
+            if !expect_operand
+                && self.starts_factor(tokens, i - 1)
+                && !matches!(
+                    (&tokens[i - 2], token),
+                    (
+                        InfixItem::Operand(_) | InfixItem::Integer(_),
+                        InfixItem::Operand(_) | InfixItem::Integer(_)
+                    )
+                )
+            {
+                if self.strict {
+                    return Err("Missing Operator".into());
+                }
+                Self::push_operator(MathOperator::Mul, token, &mut stack, &mut output);
+                expect_operand = true;
+            }
+            // postfix operators like `!` apply to the operand before them
+            // straight away, after a function whose parentheses enclose it,
+            // as in `sqrt(4)!`
+            if let InfixItem::Operator(op) = *token {
+                if op.is_postfix() && !expect_operand {
+                    if tokens[i - 2] == InfixItem::CloseParen {
+                        while let Some((top, _)) = stack.last() {
+                            let function = match *top {
+                                InfixItem::Operator(top) => top.arity() == 1 && !top.is_postfix(),
+                                InfixItem::Builtin(_) | InfixItem::Call(_) => true,
+                                _ => false,
+                            };
+                            if !function {
+                                break;
+                            }
+                            output.push_pending(stack.pop().unwrap());
                         }
-                    } else {
-                        break;
                     }
+                    output.push(RpnItem::Operator(op), token);
+                    continue;
                 }
-                stack.push(InfixItem::Operator(op));
-            }
-            InfixItem::OpenParen => stack.push(InfixItem::OpenParen),
-            InfixItem::CloseParen => loop {
-                if let Some(top) = stack.last() {
-                    if let InfixItem::OpenParen = top {
-                        stack.pop();
-                        break;
-                    } else {
-                        output.push(top.to_rpn_item().unwrap());
-                        stack.pop();
+            }
+            match *token {
+                InfixItem::Ident(ref name) if self.is_call(tokens, i - 1) => {
+                    let args = count_args(tokens, i);
+                    match self.functions[name] {
+                        Some(arity) if arity != args => {
+                            return Err(format!(
+                                "Wrong Number of Arguments: {} takes {}",
+                                name, arity
+                            ))
+                        }
+                        _ => stack.push((InfixItem::Call(name.clone()), token)),
+                    }
+                    expect_operand = true;
+                    continue;
+                }
+                InfixItem::Operand(num) => output.push(RpnItem::Operand(num), token),
+                InfixItem::Integer(n) => output.push(RpnItem::Integer(n), token),
+                InfixItem::Boolean(b) => output.push(RpnItem::Boolean(b), token),
+                InfixItem::String(ref s) => output.push(RpnItem::String(s.clone()), token),
+                InfixItem::DateTime(datetime) => output.push(RpnItem::DateTime(datetime), token),
+                InfixItem::Duration(seconds) => output.push(RpnItem::Duration(seconds), token),
+                InfixItem::Ident(ref name) => match (self.local(name), math::constant(name)) {
+                    (Some(slot), _) => output.push(RpnItem::Load(slot), token),
+                    (None, Some(num)) => output.push(RpnItem::Operand(num), token),
+                    (None, None) => {
+                        // units bind tighter than any operator, so `5 km / 2 h` is
+                        // `(5 km) / (2 h)`, and on their own they're one of the unit
+                        let unit = self.read_unit(name, tokens, &mut i)?;
+                        if expect_operand {
+                            output.push(RpnItem::Operand(1.0), token);
+                        }
+                        output.push(RpnItem::Unit(unit), token);
+                    }
+                },
+                // conversions bind looser than any operator
+                InfixItem::In => {
+                    let unit = match tokens.get(i) {
+                        Some(unit @ InfixItem::Ident(name)) => {
+                            self.at = Some(unit);
+                            i += 1;
+                            self.read_unit(name, tokens, &mut i)?
+                        }
+                        _ => return Err("Missing Unit after in".into()),
+                    };
+                    Self::pop_operators(&mut stack, &mut output);
+                    output.push(RpnItem::Convert(unit), token);
+                }
+                // the right side of `&` and `|` is compiled to a block, which is
+                // only run if the left side doesn't decide the result
+                InfixItem::Operator(op @ (MathOperator::And | MathOperator::Or)) => {
+                    Self::pop_operators(&mut stack, &mut output);
+                    let end = operand_end(tokens, i, op.precedence());
+                    let right = self.compile(&tokens[i..end])?.items;
+                    output.push(RpnItem::Block(right), token);
+                    let builtin = match op {
+                        MathOperator::And => Builtin::AndThen,
+                        _ => Builtin::OrElse,
+                    };
+                    output.push(RpnItem::Builtin(builtin), token);
+                    i = end;
+                    expect_operand = false;
+                    continue;
+                }
+                // chained comparisons like `a < b < c` are `a < b & b < c`, with
+                // `b` evaluated once into a local slot, and `b < c` only if
+                // `a < b`
+                InfixItem::Operator(op)
+                    if op.is_comparison()
+                        && matches!(
+                            tokens.get(operand_end(tokens, i, op.precedence())),
+                            Some(InfixItem::Operator(next)) if next.is_comparison()
+                        ) =>
+                {
+                    Self::pop_tighter(op, &mut stack, &mut output);
+                    let middle = operand_end(tokens, i, op.precedence());
+                    let end = operand_end(tokens, middle + 1, MathOperator::And.precedence());
+                    let slot = self.slots;
+                    self.slots += 1;
+                    output.extend(self.compile(&tokens[i..middle])?);
+                    output.push(RpnItem::Store(slot), token);
+                    output.push(RpnItem::Load(slot), token);
+                    output.push(RpnItem::Operator(op), token);
+                    let mut rest = vec![RpnItem::Load(slot)];
+                    rest.extend(self.compile(&tokens[middle..end])?.items);
+                    output.push(RpnItem::Block(rest), token);
+                    output.push(RpnItem::Builtin(Builtin::AndThen), token);
+                    i = end;
+                    expect_operand = false;
+                    continue;
+                }
+                InfixItem::Operator(op) => Self::push_operator(op, token, &mut stack, &mut output),
+                InfixItem::Builtin(builtin) => stack.push((InfixItem::Builtin(builtin), token)),
+                InfixItem::OpenParen => stack.push((InfixItem::OpenParen, token)),
+                InfixItem::CloseParen => loop {
+                    match stack.pop() {
+                        Some((InfixItem::OpenParen, _)) => break,
+                        Some(top) => output.push_pending(top),
+                        None => return Err("Mismatched Parentheses".into()),
+                    }
+                },
+                InfixItem::OpenBracket => match tokens[i..] {
+                    // interval literals like `[1.9 .. 2.1]`, widened to contain
+                    // the exact decimal numbers
+                    [InfixItem::Operand(lo), InfixItem::Operator(MathOperator::Interval), InfixItem::Operand(hi), InfixItem::CloseBracket, ..]
+                        if lo <= hi =>
+                    {
+                        output.push(RpnItem::Operand(interval::from_literal(lo).0), token);
+                        output.push(RpnItem::Operand(interval::from_literal(hi).1), token);
+                        output.push(RpnItem::Operator(MathOperator::Interval), token);
+                        i += 4;
+                        expect_operand = false;
+                        continue;
+                    }
+                    _ => {
+                        output.push(RpnItem::ListStart, token);
+                        stack.push((InfixItem::OpenBracket, token));
+                    }
+                },
+                InfixItem::CloseBracket => loop {
+                    match stack.pop() {
+                        Some((InfixItem::OpenBracket, _)) => {
+                            output.push(RpnItem::ListEnd, token);
+                            break;
+                        }
+                        Some((InfixItem::OpenParen, _)) | None => {
+                            return Err("Mismatched Brackets".into())
+                        }
+                        Some(top) => output.push_pending(top),
+                    }
+                },
+                InfixItem::Comma => loop {
+                    match stack.last() {
+                        Some((InfixItem::OpenParen | InfixItem::OpenBracket, _)) => break,
+                        Some(_) => output.push_pending(stack.pop().unwrap()),
+                        None => return Err("Misplaced Comma".into()),
+                    }
+                },
+                InfixItem::Semicolon => {
+                    loop {
+                        match stack.last() {
+                            Some((InfixItem::OpenBracket, _)) => break,
+                            Some((InfixItem::OpenParen, _)) | None => {
+                                return Err("Misplaced Semicolon".into())
+                            }
+                            Some(_) => output.push_pending(stack.pop().unwrap()),
+                        }
                     }
-                } else {
+                    output.push(RpnItem::RowEnd, token);
+                }
+                InfixItem::OpenBrace => {
+                    let end = matching_brace(tokens, i)?;
+                    let block = self.compile_block(&tokens[i..end])?;
+                    output.push(RpnItem::Block(block), token);
+                    i = end + 1;
+                }
+                InfixItem::CloseBrace => return Err("Mismatched Braces".into()),
+                InfixItem::Arrow => return Err("Misplaced ->".into()),
+                // conditionals bind looser than any operator, so the condition
+                // is everything before the `?`, and the branches are compiled to
+                // blocks so only the one taken is run
+                InfixItem::Question => {
+                    Self::pop_operators(&mut stack, &mut output);
+                    let colon = find_closing(tokens, i, &InfixItem::Question, &InfixItem::Colon)
+                        .ok_or("Missing : after ?")?;
+                    let end = group_end(tokens, colon + 1);
+                    let (then, otherwise) = (&tokens[i..colon], &tokens[colon + 1..end]);
+                    self.conditional(then, otherwise, token, &mut output)?;
+                    i = end;
+                    expect_operand = false;
+                    continue;
+                }
+                InfixItem::If => {
+                    let then = find_closing(tokens, i, &InfixItem::If, &InfixItem::Then)
+                        .ok_or("Missing then after if")?;
+                    let otherwise =
+                        find_closing(tokens, then + 1, &InfixItem::If, &InfixItem::Else)
+                            .ok_or("Missing else after then")?;
+                    let end = group_end(tokens, otherwise + 1);
+                    output.extend(self.compile(&tokens[i..then])?);
+                    self.conditional(
+                        &tokens[then + 1..otherwise],
+                        &tokens[otherwise + 1..end],
+                        token,
+                        &mut output,
+                    )?;
+                    i = end;
+                    expect_operand = false;
+                    continue;
+                }
+                // the body of a `let` goes on until the end of the parentheses,
+                // brackets or argument it's in, like an `if`'s else branch
+                InfixItem::Let => {
+                    let body = find_closing(tokens, i, &InfixItem::Let, &InfixItem::In)
+                        .ok_or("Missing in after let")?;
+                    let end = group_end(tokens, body + 1);
+                    output.extend(self.let_in(&tokens[i..body], &tokens[body + 1..end])?);
+                    i = end;
+                    expect_operand = false;
+                    continue;
+                }
+                InfixItem::Call(_) => unreachable!(),
+                InfixItem::Colon => return Err("Misplaced :".into()),
+                InfixItem::Then => return Err("Misplaced then".into()),
+                InfixItem::Else => return Err("Misplaced else".into()),
+            }
+            expect_operand = matches!(
+                token,
+                InfixItem::Operator(_)
+                    | InfixItem::Builtin(_)
+                    | InfixItem::OpenParen
+                    | InfixItem::OpenBracket
+                    | InfixItem::Comma
+                    | InfixItem::Semicolon
+            );
+        }
+
+        while let Some(top) = stack.pop() {
+            match top {
+                (InfixItem::OpenParen, source) => {
+                    self.at = Some(source);
                     return Err("Mismatched Parentheses".into());
                 }
-            },
+                (InfixItem::OpenBracket, source) => {
+                    self.at = Some(source);
+                    return Err("Mismatched Brackets".into());
+                }
+                top => output.push_pending(top),
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Compiles the branches of a conditional to blocks, and `ifte` to run the
+    /// one chosen by the condition before them, where `source` is the `?` or
+    /// `if` starting it
+    fn conditional(
+        &mut self,
+        then: &'a [InfixItem],
+        otherwise: &'a [InfixItem],
+        source: &'a InfixItem,
+        output: &mut Code<'a>,
+    ) -> Result<(), String> {
+        output.push(RpnItem::Block(self.compile(then)?.items), source);
+        output.push(RpnItem::Block(self.compile(otherwise)?.items), source);
+        output.push(RpnItem::Builtin(Builtin::Ifte), source);
+        Ok(())
+    }
+
+    /// Compiles the bindings and body of a `let`, where each binding is popped
+    /// into a local variable that's in scope in the bindings after it and in
+    /// the body
+    fn let_in(
+        &mut self,
+        bindings: &'a [InfixItem],
+        body: &'a [InfixItem],
+    ) -> Result<Code<'a>, String> {
+        let scope_len = self.scope.len();
+        let output = self.bind(bindings).and_then(|mut output| {
+            output.extend(self.compile(body)?);
+            Ok(output)
+        });
+        self.scope.truncate(scope_len);
+        output
+    }
+
+    /// Compiles comma-separated bindings like `r = 5, h = 2`, bringing each
+    /// variable into scope
+    fn bind(&mut self, bindings: &'a [InfixItem]) -> Result<Code<'a>, String> {
+        let mut output = Code::default();
+        let mut start = 0;
+        loop {
+            let end = group_end(bindings, start);
+            self.at = bindings.get(start).or(self.at);
+            let (name, value) = match &bindings[start..end] {
+                [InfixItem::Ident(name), InfixItem::Operator(MathOperator::Eq), value @ ..] => {
+                    (name, value)
+                }
+                _ => return Err("Invalid Binding".into()),
+            };
+            if value.is_empty() {
+                return Err(format!("Missing Value for {}", name));
+            }
+            output.extend(self.compile(value)?);
+            output.push(RpnItem::Store(self.slots), &bindings[start]);
+            self.scope.push((name.clone(), self.slots));
+            self.slots += 1;
+            if end == bindings.len() {
+                return Ok(output);
+            }
+            start = end + 1;
         }
     }
 
-    while let Some(top) = stack.last() {
-        if let InfixItem::OpenParen = top {
-            return Err("Mismatched Parentheses".into());
+    /// Compiles the inside of a block like `{x, y -> x + y}`, which pops its
+    /// parameters into local variables before running its body
+    fn compile_block(&mut self, tokens: &'a [InfixItem]) -> Result<Vec<RpnItem>, String> {
+        match tokens.iter().position(|t| *t == InfixItem::Arrow) {
+            Some(arrow) => self.compile_function(&tokens[..arrow], &tokens[arrow + 1..]),
+            None => self.compile_function(&[], tokens),
         }
-        output.push(top.to_rpn_item().unwrap());
-        stack.pop();
     }
 
-    Ok(output)
+    /// Compiles a body that first pops its comma-separated parameters into
+    /// local variables
+    fn compile_function(
+        &mut self,
+        params: &'a [InfixItem],
+        body: &'a [InfixItem],
+    ) -> Result<Vec<RpnItem>, String> {
+        let mut output = Vec::new();
+        let scope_len = self.scope.len();
+        for (i, param) in params.iter().enumerate() {
+            match param {
+                InfixItem::Ident(name) if i % 2 == 0 => {
+                    self.scope.push((name.clone(), self.slots));
+                    self.slots += 1;
+                }
+                InfixItem::Comma if i % 2 == 1 => {}
+                _ => {
+                    self.at = Some(param);
+                    return Err("Invalid Parameters".into());
+                }
+            }
+        }
+        for (_, slot) in self.scope[scope_len..].iter().rev() {
+            output.push(RpnItem::Store(*slot));
+        }
+
+        let body = self.compile(body);
+        self.scope.truncate(scope_len);
+        output.extend(body?.items);
+        Ok(output)
+    }
+
+    /// Reads a unit like `km/h` or `m^2`, whose first identifier `first` has
+    /// already been read, advancing `i` past the rest of it
+    fn read_unit(&self, first: &str, tokens: &[InfixItem], i: &mut usize) -> Result<Unit, String> {
+        let mut text = first.to_string();
+        loop {
+            // `m²` and `m³` are squared and cubed units, not quantities
+            let power = match tokens.get(*i) {
+                Some(InfixItem::Operator(MathOperator::Square)) => Some(2),
+                Some(InfixItem::Operator(MathOperator::Cube)) => Some(3),
+                _ => None,
+            };
+            if let Some(power) = power {
+                text += &format!("^{}", power);
+                *i += 1;
+                continue;
+            }
+            match tokens.get(*i..*i + 2) {
+                Some([InfixItem::Operator(MathOperator::Pow), InfixItem::Operand(exp)])
+                    if exp.fract() == 0.0 =>
+                {
+                    text += &format!("^{}", exp);
+                }
+                Some(
+                    [InfixItem::Operator(op @ (MathOperator::Mul | MathOperator::Div)), InfixItem::Ident(name)],
+                ) if self.local(name).is_none() && name.parse::<Unit>().is_ok() => {
+                    text += &format!("{}{}", op, name);
+                }
+                _ => break,
+            }
+            *i += 2;
+        }
+        text.parse()
+            .map_err(|_| format!("Unknown Identifier: {}", first))
+    }
+}
+
+/// Finds the index of the `close` matching an `open` before `start`, like the
+/// `:` of a `?`, outside of any brackets and nested pairs
+fn find_closing(
+    tokens: &[InfixItem],
+    start: usize,
+    open: &InfixItem,
+    close: &InfixItem,
+) -> Option<usize> {
+    let mut depth = 0;
+    let mut nesting = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            InfixItem::OpenParen | InfixItem::OpenBracket | InfixItem::OpenBrace => depth += 1,
+            InfixItem::CloseParen | InfixItem::CloseBracket | InfixItem::CloseBrace => {
+                if depth == 0 {
+                    return None;
+                }
+                depth -= 1;
+            }
+            token if depth == 0 && token == open => nesting += 1,
+            token if depth == 0 && token == close && nesting == 0 => return Some(i),
+            token if depth == 0 && token == close => nesting -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Finds the end of the right operand starting at `start` of an operator with
+/// the given precedence, which is the next operator that binds as loosely, or
+/// anything looser
+fn operand_end(tokens: &[InfixItem], start: usize, precedence: u8) -> usize {
+    let end = group_end(tokens, start);
+    let mut depth = 0;
+    for (i, token) in tokens[..end].iter().enumerate().skip(start) {
+        match token {
+            InfixItem::OpenParen | InfixItem::OpenBracket | InfixItem::OpenBrace => depth += 1,
+            InfixItem::CloseParen | InfixItem::CloseBracket | InfixItem::CloseBrace => depth -= 1,
+            // an `if`'s else branch and a `let`'s body go on until the end of
+            // the group anyway
+            InfixItem::If | InfixItem::Let if depth == 0 => return end,
+            InfixItem::Operator(op)
+                if depth == 0 && !op.is_postfix() && op.precedence() <= precedence =>
+            {
+                return i
+            }
+            InfixItem::Question
+            | InfixItem::Colon
+            | InfixItem::Then
+            | InfixItem::Else
+            | InfixItem::In
+                if depth == 0 =>
+            {
+                return i
+            }
+            _ => {}
+        }
+    }
+    end
+}
+
+/// Finds the end of the parentheses, brackets, list element or function
+/// argument that `start` is in
+fn group_end(tokens: &[InfixItem], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            InfixItem::OpenParen | InfixItem::OpenBracket | InfixItem::OpenBrace => depth += 1,
+            InfixItem::CloseParen
+            | InfixItem::CloseBracket
+            | InfixItem::CloseBrace
+            | InfixItem::Comma
+            | InfixItem::Semicolon
+                if depth == 0 =>
+            {
+                return i
+            }
+            InfixItem::CloseParen | InfixItem::CloseBracket | InfixItem::CloseBrace => depth -= 1,
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// Counts the arguments in the parentheses opening at `open`
+fn count_args(tokens: &[InfixItem], open: usize) -> usize {
+    let end = group_end(tokens, open + 1);
+    if end == open + 1 {
+        return 0;
+    }
+    let mut args = 1;
+    let mut i = end;
+    while tokens.get(i) == Some(&InfixItem::Comma) {
+        args += 1;
+        i = group_end(tokens, i + 1);
+    }
+    args
+}
+
+/// Finds the index of the brace closing a block whose contents start at `start`
+fn matching_brace(tokens: &[InfixItem], start: usize) -> Result<usize, String> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            InfixItem::OpenBrace => depth += 1,
+            InfixItem::CloseBrace if depth == 0 => return Ok(i),
+            InfixItem::CloseBrace => depth -= 1,
+            _ => {}
+        }
+    }
+    Err("Mismatched Braces".into())
+}
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+    use crate::{math::Value, rpn::execute_rpn};
+
+    #[allow(dead_code)]
+    fn eval(s: &str) -> Result<Value, String> {
+        execute_rpn(&infix_to_rpn(&parse_infix(s)?)?)
+    }
+
+    #[test]
+    fn test_infix_to_rpn() {
+        assert_eq!(
+            infix_to_rpn(&parse_infix("1+2*-3").unwrap()).unwrap(),
+            vec![
+                RpnItem::Operand(1.0),
+                RpnItem::Operand(2.0),
+                RpnItem::Operand(-3.0),
+                RpnItem::Operator(MathOperator::Mul),
+                RpnItem::Operator(MathOperator::Add),
+            ]
+        );
+        assert_eq!(
+            infix_to_rpn(&parse_infix("map([1], {x -> x})").unwrap()).unwrap(),
+            vec![
+                RpnItem::ListStart,
+                RpnItem::Operand(1.0),
+                RpnItem::ListEnd,
+                RpnItem::Block(vec![RpnItem::Store(0), RpnItem::Load(0)]),
+                RpnItem::Builtin(Builtin::Map),
+            ]
+        );
+        assert!(infix_to_rpn(&parse_infix("[1, (2]").unwrap()).is_err());
+        assert!(infix_to_rpn(&parse_infix("{1").unwrap()).is_err());
+        assert!(infix_to_rpn(&parse_infix("x + 1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_infix_units() {
+        assert_eq!(
+            eval("(5 km) / (2 h) in km/h"),
+            Ok(Value::Quantity(2.5, "km/h".parse().unwrap()))
+        );
+        assert_eq!(
+            eval("5 m - 3 m"),
+            Ok(Value::Quantity(2.0, "m".parse().unwrap()))
+        );
+        assert_eq!(
+            eval("sqrt(16 m^2)"),
+            Ok(Value::Quantity(4.0, "m".parse().unwrap()))
+        );
+        assert_eq!(eval("10 / s * 2 s"), Ok(Value::Number(20.0)));
+    }
+
+    #[test]
+    fn test_infix_lists() {
+        let list = |xs: &[f64]| Value::List(xs.iter().map(|x| Value::Number(*x)).collect());
+        assert_eq!(eval("[1, 2, 3] * 2 + 1"), Ok(list(&[3.0, 5.0, 7.0])));
+        assert_eq!(
+            eval("map(range(0, 3), {x -> x ^ 2})"),
+            Ok(list(&[0.0, 1.0, 4.0]))
+        );
+        assert_eq!(
+            eval("[0] ++ concat([1], [2]) ++ [3]"),
+            Ok(list(&[0.0, 1.0, 2.0, 3.0]))
+        );
+        assert_eq!(
+            eval("fold([1, 2, 3], 0, {acc, x -> acc + map([x], {y -> y * x})})"),
+            Ok(list(&[14.0]))
+        );
+        assert_eq!(eval("nth(reverse([1, 2, 3]), 0)"), Ok(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_infix_matrices() {
+        assert_eq!(eval("det([1, 2; 3, 4] * 2)"), Ok(Value::Number(-8.0)));
+        assert_eq!(
+            eval("solve([1, 1; 1, -1], [3, 1])"),
+            Ok(Value::List(vec![Value::Number(2.0), Value::Number(1.0)]))
+        );
+        assert!(eval("[1, 2; 3]").is_err());
+    }
+
+    #[test]
+    fn test_infix_intervals() {
+        assert_eq!(eval("[1 .. 2] + [3 .. 4]"), Ok(Value::Interval(4.0, 6.0)));
+        assert_eq!(eval("[1..2] * -1"), Ok(Value::Interval(-2.0, -1.0)));
+        assert_eq!(eval("2 * 3 ± 1"), Ok(Value::Interval(5.0, 7.0)));
+        assert_eq!(eval("sqrt([4 .. 9])"), Ok(Value::Interval(2.0, 3.0)));
+        assert_eq!(
+            eval("[1 .. 2] < [2 .. 3]"),
+            Err("ambiguous comparison: <".into())
+        );
+        assert_eq!(eval("[1 .. 2] <= [2 .. 3]"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("[1 .. 2] / 0"), Err("division by zero: /".into()));
+        assert_eq!(
+            eval("[1 .. 2]!"),
+            Err("unsupported for interval: fact".into())
+        );
+        assert_eq!(
+            eval("[1 .. 2] + 1 m"),
+            Err("unsupported for interval: +".into())
+        );
+        assert_eq!(eval("2 m ± 1 m"), Err("unsupported for interval: ±".into()));
+        assert_eq!(
+            eval("[1 .. 2, 3]"),
+            Ok(Value::List(vec![
+                Value::Interval(1.0, 2.0),
+                Value::Number(3.0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_infix_measurements() {
+        assert_eq!(eval("9.81+/-0.02"), Ok(Value::Measurement(9.81, 0.02)));
+        assert_eq!(
+            eval("(6 +/- 3) / (2 +/- 1)"),
+            Ok(Value::Measurement(3.0, 1.5f64.hypot(1.5)))
+        );
+        assert_eq!(
+            eval("(3 +/- 0.1)^2"),
+            Ok(Value::Measurement(9.0, 0.6000000000000001))
+        );
+        // shown as it's written, since `±` would be an interval
+        assert_eq!(
+            eval("str(1 +/- 0.5)"),
+            Ok(Value::String("1 +/- 0.5".into()))
+        );
+        let shown = eval("2 +/- 0.25").unwrap().to_string();
+        assert_eq!(eval(&shown), Ok(Value::Measurement(2.0, 0.25)));
+        assert_eq!(
+            eval("(5 +/- 1)!"),
+            Err("unsupported for measurement: fact".to_string())
+        );
+        assert_eq!(
+            eval("2 m +/- 1 m"),
+            Err("unsupported for measurement: +/-".to_string())
+        );
+    }
+
+    #[test]
+    fn test_infix_dates() {
+        let eval = |s: &str| eval(s).map(|v| v.to_string());
+        assert_eq!(
+            eval("2026-10-17+PT3H30M"),
+            Ok("2026-10-17T03:30:00Z".into())
+        );
+        assert_eq!(eval("2026-10-17-PT1H"), Ok("2026-10-16T23:00:00Z".into()));
+        assert_eq!(eval("2026-10-17 + 2 day"), Ok("2026-10-19".into()));
+        assert_eq!(eval("(2027-01-01 - 2026-10-17) / P1D"), Ok("76".into()));
+        assert_eq!(eval("-PT1H * 2"), Ok("-PT2H".into()));
+        assert_eq!(
+            eval("weekday(2026-10-17T23:30-02:00)"),
+            Ok("Saturday".into())
+        );
+        assert_eq!(
+            eval("format(2026-10-17T09:30:15.5+01:00, \"%a %d %b %H:%M:%S\")"),
+            Ok("Sat 17 Oct 09:30:15".into())
+        );
+    }
+
+    #[test]
+    fn test_infix_bitwise() {
+        assert_eq!(eval("0xF0 or 0x0F and 0x3C"), Ok(Value::Number(252.0)));
+        assert_eq!(eval("1 shl 2 + 1"), Ok(Value::Number(8.0)));
+        assert_eq!(eval("0xFF and 0x0F = 0x0F"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("not(0b101) and 0b111"), Ok(Value::Number(2.0)));
+        assert_eq!(eval("popcount(1_023)"), Ok(Value::Number(10.0)));
+        assert_eq!(eval("-0x10"), Ok(Value::Number(-16.0)));
+    }
+
+    #[test]
+    fn test_infix_number_literals() {
+        assert_eq!(eval("1e-3*2"), Ok(Value::Number(0.002)));
+        assert_eq!(eval("2.5E3 - 1e+3"), Ok(Value::Number(1500.0)));
+        assert_eq!(eval(".5 + 1_000"), Ok(Value::Number(1000.5)));
+        assert_eq!(eval("-inf < 1e308"), Ok(Value::Boolean(true)));
+        assert!(matches!(eval("nan + 1"), Ok(Value::Number(x)) if x.is_nan()));
+        assert_eq!(
+            parse_infix("1.2.3 + 1"),
+            Err("Invalid Number: 1.2.3".to_string())
+        );
+        assert_eq!(
+            parse_infix("1e5.5"),
+            Err("Invalid Number: 1e5.5".to_string())
+        );
+        for number in ["1e+", "1__0", "0x", "2e3e4", "0x1.8", "1_"] {
+            assert_eq!(
+                parse_infix(&format!("{} + 1", number)),
+                Err(format!("Invalid Number: {}", number))
+            );
+        }
+        // a dangling exponent is malformed, rather than a multiple of `e`
+        assert_eq!(parse_infix("1e"), Err("Invalid Number: 1e".to_string()));
+        assert_eq!(eval("2e+1"), Ok(Value::Number(20.0)));
+        assert_eq!(parse_infix("2e + 1"), Err("Invalid Number: 2e".to_string()));
+        assert_eq!(parse_infix("2e-x"), Err("Invalid Number: 2e-".to_string()));
+        assert_eq!(eval("2 e"), Ok(Value::Number(2.0 * std::f64::consts::E)));
+        assert_eq!(eval("0x10..0x20"), Ok(Value::Interval(16.0, 32.0)));
+    }
+
+    #[test]
+    fn test_infix_implicit_multiplication() {
+        assert_eq!(eval("3(4+5)"), Ok(Value::Number(27.0)));
+        assert_eq!(eval("(1+2)(3+4)"), Ok(Value::Number(21.0)));
+        assert_eq!(eval("2pi"), Ok(Value::Number(2.0 * std::f64::consts::PI)));
+        assert_eq!(eval("1/2pi"), Ok(Value::Number(std::f64::consts::PI / 2.0)));
+        assert_eq!(eval("2sqrt(16)"), Ok(Value::Number(8.0)));
+        assert_eq!(
+            eval("map([1, 2], {x -> 2x^2})"),
+            Ok(Value::List(vec![Value::Number(2.0), Value::Number(8.0)]))
+        );
+        assert_eq!(
+            eval("fold([1, 2], 3, {a, b -> (a+b)(a-b)})"),
+            Ok(Value::Number(60.0))
+        );
+        assert_eq!(
+            eval("2 km (3)"),
+            Ok(Value::Quantity(6.0, "km".parse().unwrap()))
+        );
+        assert!(eval("2 3").is_err());
+        let strict = |s| {
+            let (tokens, offsets) = parse_infix_with_offsets(s).unwrap();
+            infix_to_rpn_with(&tokens, &offsets, &Interpreter::default(), true)
+        };
+        assert_eq!(
+            strict("3(4+5)"),
+            Err(LineError {
+                message: "Missing Operator".into(),
+                offset: 1
+            })
+        );
+        assert!(strict("3*(4+5) km").is_ok());
+    }
+
+    #[test]
+    fn test_infix_postfix() {
+        assert_eq!(eval("3!+1"), Ok(Value::Number(7.0)));
+        assert_eq!(eval("2^3!"), Ok(Value::Number(64.0)));
+        assert_eq!(eval("sqrt(16)!"), Ok(Value::Number(24.0)));
+        assert_eq!(eval("3! - 1"), Ok(Value::Number(5.0)));
+        assert_eq!(eval("3!!"), Ok(Value::Number(720.0)));
+        assert_eq!(eval("-3!"), Ok(Value::Number(-6.0)));
+        assert_eq!(eval("-3!!"), Ok(Value::Number(-720.0)));
+        assert_eq!(eval("1 - -3! * 2"), Ok(Value::Number(13.0)));
+        assert_eq!(eval("2^-3!"), Ok(Value::Number(1.0 / 64.0)));
+        assert_eq!(eval("-3² + 1"), Ok(Value::Number(-8.0)));
+        assert_eq!(eval("-3^2"), Ok(Value::Number(9.0)));
+        assert_eq!(eval("!(1 != 2)"), Ok(Value::Boolean(false)));
+        assert_eq!(eval("200 * 15%"), Ok(Value::Number(30.0)));
+        assert_eq!(eval("3²+4³"), Ok(Value::Number(73.0)));
+        assert_eq!(
+            eval("2 m²"),
+            Ok(Value::Quantity(2.0, "m^2".parse().unwrap()))
+        );
+        assert_eq!(
+            eval("(3 m)²"),
+            Ok(Value::Quantity(9.0, "m^2".parse().unwrap()))
+        );
+        assert_eq!(
+            infix_to_rpn(&parse_infix("!true & 4! > 5").unwrap()).unwrap(),
+            vec![
+                RpnItem::Boolean(true),
+                RpnItem::Operator(MathOperator::Not),
+                RpnItem::Block(vec![
+                    RpnItem::Operand(4.0),
+                    RpnItem::Operator(MathOperator::Factorial),
+                    RpnItem::Operand(5.0),
+                    RpnItem::Operator(MathOperator::Gt),
+                ]),
+                RpnItem::Builtin(Builtin::AndThen),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infix_conditionals() {
+        assert_eq!(eval("1 + (2 > 3 ? 10 : 20) * 2"), Ok(Value::Number(41.0)));
+        assert_eq!(eval("true ? false ? 1 : 2 : 3"), Ok(Value::Number(2.0)));
+        assert_eq!(eval("false ? 1 : true ? 2 : 3"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            eval("[true ? 1 : 2, 3]"),
+            Ok(Value::List(vec![Value::Number(1.0), Value::Number(3.0)]))
+        );
+        assert_eq!(
+            eval("2 * if true then if false then 1 else 2 else 3"),
+            Ok(Value::Number(4.0))
+        );
+        assert_eq!(
+            eval("map([-2, 3], {x -> x < 0 ? 0 - x : x})"),
+            Ok(Value::List(vec![Value::Number(2.0), Value::Number(3.0)]))
+        );
+        // the branch not taken isn't run
+        assert_eq!(
+            eval(r#"1 > 2 ? "a" + 1 : "b""#),
+            Ok(Value::String("b".into()))
+        );
+        assert_eq!(eval("1 ? 2 : 3"), Err("invalid type: ifte".into()));
+        assert_eq!(eval("true ? 1"), Err("Missing : after ?".into()));
+        assert_eq!(
+            eval("if true then 1"),
+            Err("Missing else after then".into())
+        );
+        assert_eq!(eval("(true ? 1) : 2"), Err("Missing : after ?".into()));
+        assert_eq!(eval("1 else 2"), Err("Misplaced else".into()));
+    }
+
+    #[test]
+    fn test_infix_short_circuit() {
+        assert_eq!(eval(r#"false & "a" + 1 > 0"#), Ok(Value::Boolean(false)));
+        assert_eq!(eval(r#"true | "a" + 1 > 0"#), Ok(Value::Boolean(true)));
+        assert_eq!(eval(r#"true & "a" + 1 > 0"#), Err("invalid type: +".into()));
+        assert_eq!(eval("true & false | true"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("1 < 2 & 2 < 3 & 3 > 4"), Ok(Value::Boolean(false)));
+        assert_eq!(
+            eval("fold([0, 2], 0, {a, x -> x != 0 & 4 / x > 1 ? a + 1 : a})"),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(eval("true & 5"), Err("invalid type: andthen".into()));
+        assert_eq!(eval("5 | true"), Err("invalid type: orelse".into()));
+    }
+
+    #[test]
+    fn test_infix_chained_comparisons() {
+        assert_eq!(eval("1 < 3 < 5"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("1 < 7 < 5"), Ok(Value::Boolean(false)));
+        assert_eq!(eval("1 < 2 + 1 <= 3 != 4"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("5 > 3 > 4"), Ok(Value::Boolean(false)));
+        assert_eq!(
+            eval("map([0, 3, 9], {x -> 1 < x < 5})"),
+            Ok(Value::List(vec![
+                Value::Boolean(false),
+                Value::Boolean(true),
+                Value::Boolean(false)
+            ]))
+        );
+        assert_eq!(eval("1 < 3 < 5 ? 10 : 20"), Ok(Value::Number(10.0)));
+        // the rest of the chain isn't evaluated once it's false
+        assert_eq!(eval(r#"1 > 2 < "a" + 1"#), Ok(Value::Boolean(false)));
+        // and the middle operand is evaluated once
+        assert_eq!(
+            infix_to_rpn(&parse_infix("1 < 2 * 3 < 9").unwrap()).unwrap(),
+            vec![
+                RpnItem::Operand(1.0),
+                RpnItem::Operand(2.0),
+                RpnItem::Operand(3.0),
+                RpnItem::Operator(MathOperator::Mul),
+                RpnItem::Store(0),
+                RpnItem::Load(0),
+                RpnItem::Operator(MathOperator::Lt),
+                RpnItem::Block(vec![
+                    RpnItem::Load(0),
+                    RpnItem::Operand(9.0),
+                    RpnItem::Operator(MathOperator::Lt),
+                ]),
+                RpnItem::Builtin(Builtin::AndThen),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infix_functions() {
+        let interpreter = Interpreter::default();
+        let define = |s| {
+            let (tokens, offsets) = parse_infix_with_offsets(s).unwrap();
+            define_function(&tokens, &offsets, s, &interpreter, false)
+                .map(|defined| defined.map_err(|e| e.message))
+        };
+        let eval = |s| {
+            let (tokens, offsets) = parse_infix_with_offsets(s).unwrap();
+            let (rpn, offsets) =
+                infix_to_rpn_with(&tokens, &offsets, &interpreter, false).map_err(|e| e.message)?;
+            interpreter
+                .execute_with_offsets(&rpn, &offsets)
+                .map_err(|e| e.message)
+        };
+        assert_eq!(define("f(x, y) = x^2 + y"), Some(Ok(())));
+        assert_eq!(eval("f(3, 4)"), Ok(Value::Number(13.0)));
+        assert_eq!(eval("2f(1, 1) + 1"), Ok(Value::Number(5.0)));
+        assert_eq!(
+            eval("f(1)"),
+            Err("Wrong Number of Arguments: f takes 2".to_string())
+        );
+        assert_eq!(define("g(n) = n <= 1 ? 1 : n * g(n - 1)"), Some(Ok(())));
+        assert_eq!(eval("g(5)"), Ok(Value::Number(120.0)));
+        assert_eq!(define("h(n) = h(n)"), Some(Ok(())));
+        assert_eq!(eval("h(1)"), Err("recursion too deep: h".to_string()));
+        assert!(interpreter.warnings.borrow().is_empty());
+        assert_eq!(define("f(x) = x"), Some(Ok(())));
+        assert_eq!(
+            interpreter.warnings.take(),
+            vec!["redefining f".to_string()]
+        );
+        assert_eq!(interpreter.words.borrow()["f"].source, "f(x) = x");
+        assert_eq!(eval("f(2)"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            define("f(x) ="),
+            Some(Err("Missing Body after =".to_string()))
+        );
+        assert_eq!(define("f(2) + 1"), None);
+        assert_eq!(define("x = 1"), None);
+        assert_eq!(
+            define("fact(n) = n <= 1 ? 1 : n * fact(n - 1)"),
+            Some(Err("Cannot Redefine Builtin: fact".to_string()))
+        );
+        assert_eq!(
+            define("len(x) = 3"),
+            Some(Err("Cannot Redefine Builtin: len".to_string()))
+        );
+        assert_eq!(
+            define("map(xs, f) = xs"),
+            Some(Err("Cannot Redefine Builtin: map".to_string()))
+        );
+        assert_eq!(define("sqrt(pi) = 1"), None);
+        assert_eq!(define("len([1]) = 1"), None);
+    }
+
+    #[test]
+    fn test_infix_offsets() {
+        let eval = |s| {
+            let interpreter = Interpreter::default();
+            let (tokens, offsets) = parse_infix_with_offsets(s)?;
+            let (rpn, offsets) = infix_to_rpn_with(&tokens, &offsets, &interpreter, false)?;
+            interpreter.execute_with_offsets(&rpn, &offsets)
+        };
+        let error = |message: &str, offset| {
+            Err(LineError {
+                message: message.into(),
+                offset,
+            })
+        };
+        assert_eq!(eval("1 + $"), error("Invalid Operator: $", 4));
+        assert_eq!(eval("2*(3 + 4"), error("Mismatched Parentheses", 2));
+        assert_eq!(eval("1 +\n  x"), error("Unknown Identifier: x", 6));
+        assert_eq!(
+            eval("5 m in furlong"),
+            error("Unknown Identifier: furlong", 7)
+        );
+        assert_eq!(eval("let a = 1, 2 in a"), error("Invalid Binding", 11));
+        assert_eq!(eval("[1, 2] * 3 + \"a\""), error("invalid type: +", 11));
+        assert_eq!(eval("\"é\" + len(1)"), error("invalid type: len", 7));
+        assert_eq!(eval("2 km in s"), error("invalid type: in s", 5));
+    }
+
+    #[test]
+    fn test_infix_let() {
+        assert_eq!(
+            eval("let r = 5, h = 2 in pi r^2 h"),
+            Ok(Value::Number(std::f64::consts::PI * 50.0))
+        );
+        assert_eq!(
+            eval("let x = 3, y = x + 1 in x * y"),
+            Ok(Value::Number(12.0))
+        );
+        assert_eq!(eval("1 + (let x = 2 in x^2) * 3"), Ok(Value::Number(13.0)));
+        assert_eq!(
+            eval("let x = 1 in let x = x + 1 in x * 10"),
+            Ok(Value::Number(20.0))
+        );
+        assert_eq!(
+            eval("let h = 2 h in h in min"),
+            Ok(Value::Quantity(120.0, "min".parse().unwrap()))
+        );
+        assert_eq!(
+            eval("map([1, 2], {x -> let y = x * 2 in y + 1})"),
+            Ok(Value::List(vec![Value::Number(3.0), Value::Number(5.0)]))
+        );
+        assert_eq!(eval("let x = 2"), Err("Missing in after let".into()));
+        assert_eq!(eval("let in 3"), Err("Invalid Binding".into()));
+        assert_eq!(eval("let x = 1, in x"), Err("Invalid Binding".into()));
+        assert_eq!(eval("let x = in 3"), Err("Missing Value for x".into()));
+        assert_eq!(
+            eval("(let x = 2 in x) + x"),
+            Err("Unknown Identifier: x".into())
+        );
+    }
+
+    #[test]
+    fn test_infix_is_incomplete() {
+        assert!(is_incomplete("f(3,"));
+        assert!(is_incomplete("[1, 2"));
+        assert!(is_incomplete("1 +"));
+        assert!(is_incomplete("f(x) ="));
+        assert!(is_incomplete("let x = 1,"));
+        assert!(is_incomplete("if x > 0 then"));
+        assert!(!is_incomplete("1 + 2"));
+        assert!(!is_incomplete("3!"));
+        assert!(!is_incomplete("50%"));
+        assert!(!is_incomplete("(1 + 2))"));
+    }
+
+    #[test]
+    fn test_infix_parse_import() {
+        assert_eq!(
+            parse_import("import \"stats.inf\""),
+            Some("stats.inf".into())
+        );
+        assert_eq!(parse_import("import stats"), None);
+        assert_eq!(parse_import("len(\"stats.inf\")"), None);
+    }
 }
@@ -0,0 +1,250 @@
+//! Interval arithmetic on `(lo, hi)` bounds, rounding every result outward so
+//! the true result is always inside the bounds.
+
+use std::cmp::Ordering;
+
+pub type Bounds = (f64, f64);
+
+/// Bounds for the exact value `r + err`, where `r` is its nearest float
+fn round(r: f64, err: f64) -> Bounds {
+    if r.is_infinite() {
+        // overflowed, so the exact value is past the largest finite float
+        return if r > 0.0 {
+            (f64::MAX, r)
+        } else {
+            (r, f64::MIN)
+        };
+    }
+    match err.partial_cmp(&0.0) {
+        Some(Ordering::Less) => (r.next_down(), r),
+        Some(Ordering::Greater) => (r, r.next_up()),
+        _ => (r, r),
+    }
+}
+
+fn add_rounded(a: f64, b: f64) -> Bounds {
+    // the error of the sum, exactly (Knuth's TwoSum)
+    let s = a + b;
+    let bb = s - a;
+    let err = (a - (s - bb)) + (b - bb);
+    round(s, err)
+}
+
+fn mul_rounded(a: f64, b: f64) -> Bounds {
+    // 0 * inf is 0 here, since an infinite bound only means "unbounded"
+    if a == 0.0 || b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let p = a * b;
+    round(p, a.mul_add(b, -p))
+}
+
+fn div_rounded(a: f64, b: f64) -> Bounds {
+    if a == 0.0 {
+        return (0.0, 0.0);
+    }
+    let q = a / b;
+    // a = q * b + rem, so the exact quotient is q + rem / b
+    let rem = -q.mul_add(b, -a);
+    round(q, rem * b.signum())
+}
+
+fn sqrt_rounded(x: f64) -> Bounds {
+    let r = x.sqrt();
+    round(r, -r.mul_add(r, -x))
+}
+
+/// Widens bounds computed by functions that aren't correctly rounded, like
+/// `powf`, by two units in the last place
+fn widen((lo, hi): Bounds) -> Bounds {
+    (lo.next_down().next_down(), hi.next_up().next_up())
+}
+
+/// The smallest interval containing every lower and upper bound
+fn hull(bounds: &[Bounds]) -> Bounds {
+    bounds
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), b| {
+            (lo.min(b.0), hi.max(b.1))
+        })
+}
+
+pub fn contains_zero((lo, hi): Bounds) -> bool {
+    lo <= 0.0 && 0.0 <= hi
+}
+
+/// The interval of a decimal literal, which may not be exactly representable
+pub fn from_literal(x: f64) -> Bounds {
+    if x.fract() == 0.0 {
+        (x, x)
+    } else {
+        (x.next_down(), x.next_up())
+    }
+}
+
+/// `x ± r`
+pub fn plus_minus(x: f64, r: f64) -> Bounds {
+    (add_rounded(x, -r.abs()).0, add_rounded(x, r.abs()).1)
+}
+
+pub fn add(x: Bounds, y: Bounds) -> Bounds {
+    (add_rounded(x.0, y.0).0, add_rounded(x.1, y.1).1)
+}
+
+pub fn sub(x: Bounds, y: Bounds) -> Bounds {
+    (add_rounded(x.0, -y.1).0, add_rounded(x.1, -y.0).1)
+}
+
+pub fn mul(x: Bounds, y: Bounds) -> Bounds {
+    hull(&[
+        mul_rounded(x.0, y.0),
+        mul_rounded(x.0, y.1),
+        mul_rounded(x.1, y.0),
+        mul_rounded(x.1, y.1),
+    ])
+}
+
+/// Divides intervals, giving unbounded results for divisors containing zero,
+/// or `None` if the divisor is exactly zero
+pub fn div(x: Bounds, y: Bounds) -> Option<Bounds> {
+    const ALL: Bounds = (f64::NEG_INFINITY, f64::INFINITY);
+    if y == (0.0, 0.0) {
+        return None;
+    }
+    if !contains_zero(y) {
+        return Some(hull(&[
+            div_rounded(x.0, y.0),
+            div_rounded(x.0, y.1),
+            div_rounded(x.1, y.0),
+            div_rounded(x.1, y.1),
+        ]));
+    }
+    if contains_zero(x) || (y.0 < 0.0 && y.1 > 0.0) {
+        return Some(ALL);
+    }
+    // the divisor touches zero at one end, so the result is a half-line
+    Some(match (x.1 < 0.0, y.0 == 0.0) {
+        (true, true) => (f64::NEG_INFINITY, div_rounded(x.1, y.1).1),
+        (true, false) => (div_rounded(x.1, y.0).0, f64::INFINITY),
+        (false, true) => (div_rounded(x.0, y.1).0, f64::INFINITY),
+        (false, false) => (f64::NEG_INFINITY, div_rounded(x.0, y.0).1),
+    })
+}
+
+/// Square root, or `None` if the interval is entirely negative
+pub fn sqrt(x: Bounds) -> Option<Bounds> {
+    if x.1 < 0.0 {
+        return None;
+    }
+    Some((sqrt_rounded(x.0.max(0.0)).0, sqrt_rounded(x.1).1))
+}
+
+/// Raises an interval to a power, or `None` if it's undefined somewhere in
+/// the intervals
+pub fn pow(x: Bounds, y: Bounds) -> Option<Bounds> {
+    if y.0 == y.1 && y.0.fract() == 0.0 {
+        let n = y.0;
+        if n == 0.0 {
+            return Some((1.0, 1.0));
+        }
+        if n < 0.0 {
+            return div((1.0, 1.0), pow(x, (-n, -n))?);
+        }
+        let (lo, hi) = (x.0.powf(n), x.1.powf(n));
+        // even powers have their minimum at zero
+        return Some(widen(if n % 2.0 != 0.0 {
+            (lo, hi)
+        } else if contains_zero(x) {
+            (0.0, lo.max(hi))
+        } else {
+            (lo.min(hi), lo.max(hi))
+        }));
+    }
+    // otherwise x^y is only defined for non-negative x, where it's monotonic
+    // in both x and y
+    if x.0 < 0.0 {
+        return None;
+    }
+    let corners = [x.0.powf(y.0), x.0.powf(y.1), x.1.powf(y.0), x.1.powf(y.1)];
+    Some(widen(hull(&corners.map(|c| (c, c)))))
+}
+
+/// Whether every value in `x` is less than (or equal to) every value in `y`,
+/// or `None` if it depends on where in the intervals the values are
+pub fn less(x: Bounds, y: Bounds, or_equal: bool) -> Option<bool> {
+    if x.1 < y.0 || (or_equal && x.1 <= y.0) {
+        Some(true)
+    } else if x.0 > y.1 || (!or_equal && x.0 >= y.1) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Whether the intervals are equal, or `None` if they overlap without both
+/// being the same single number
+pub fn equal(x: Bounds, y: Bounds) -> Option<bool> {
+    if x.1 < y.0 || x.0 > y.1 {
+        Some(false)
+    } else if x.0 == x.1 && x == y {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_interval_rounding() {
+        // 0.1 + 0.2 isn't exactly 0.3 as a float, so the bounds are around it
+        let (lo, hi) = add((0.1, 0.1), (0.2, 0.2));
+        assert!(lo < hi);
+        assert!(lo == 0.1 + 0.2 || hi == 0.1 + 0.2);
+        assert_eq!(add((1.0, 2.0), (3.0, 4.0)), (4.0, 6.0));
+
+        let (lo, hi) = div((1.0, 1.0), (3.0, 3.0)).unwrap();
+        assert_eq!(hi, lo.next_up());
+
+        let (lo, hi) = sqrt((2.0, 2.0)).unwrap();
+        assert!(lo * lo <= 2.0 && hi * hi >= 2.0);
+        assert_eq!(sqrt((4.0, 9.0)), Some((2.0, 3.0)));
+
+        assert_eq!(from_literal(2.0), (2.0, 2.0));
+        let (lo, hi) = from_literal(1.9);
+        assert!(lo < 1.9 && hi > 1.9);
+    }
+
+    #[test]
+    fn test_interval_ops() {
+        assert_eq!(sub((1.0, 2.0), (3.0, 5.0)), (-4.0, -1.0));
+        assert_eq!(mul((-1.0, 2.0), (3.0, 4.0)), (-4.0, 8.0));
+        assert_eq!(mul((-2.0, -1.0), (-3.0, 4.0)), (-8.0, 6.0));
+        assert_eq!(div((1.0, 2.0), (4.0, 8.0)), Some((0.125, 0.5)));
+        assert_eq!(div((1.0, 2.0), (0.0, 0.0)), None);
+        assert_eq!(div((1.0, 2.0), (0.0, 4.0)), Some((0.25, f64::INFINITY)));
+        assert_eq!(
+            div((1.0, 2.0), (-4.0, 0.0)),
+            Some((f64::NEG_INFINITY, -0.25))
+        );
+        assert_eq!(
+            div((1.0, 2.0), (-1.0, 1.0)),
+            Some((f64::NEG_INFINITY, f64::INFINITY))
+        );
+
+        let (lo, hi) = pow((-2.0, 3.0), (2.0, 2.0)).unwrap();
+        assert!((-1e-300..=0.0).contains(&lo) && (9.0..9.0 + 1e-12).contains(&hi));
+        assert_eq!(pow((-1.0, 2.0), (0.5, 0.5)), None);
+
+        assert_eq!(less((1.0, 2.0), (3.0, 4.0), false), Some(true));
+        assert_eq!(less((3.0, 4.0), (1.0, 2.0), false), Some(false));
+        assert_eq!(less((1.0, 3.0), (2.0, 4.0), false), None);
+        assert_eq!(less((1.0, 2.0), (2.0, 3.0), false), None);
+        assert_eq!(less((1.0, 2.0), (2.0, 3.0), true), Some(true));
+        assert_eq!(equal((2.0, 2.0), (2.0, 2.0)), Some(true));
+        assert_eq!(equal((1.0, 2.0), (3.0, 4.0)), Some(false));
+        assert_eq!(equal((1.0, 2.0), (1.0, 2.0)), None);
+    }
+}
@@ -1,4 +1,10 @@
+pub mod cli;
+pub mod datetime;
+pub mod format;
 pub mod infix;
+pub mod interval;
 pub mod math;
+pub mod matrix;
 pub mod rpn;
+pub mod units;
 pub mod utils;
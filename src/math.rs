@@ -1,16 +1,195 @@
-use std::{fmt::Display, str::FromStr};
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Write},
+    str::FromStr,
+};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+use crate::{
+    datetime::{self, DateTime},
+    format::Format,
+    interval::{self, Bounds},
+    matrix::{Matrix, MatrixError, MAX_SIZE},
+    rpn::RpnItem,
+    units::{Dimension, Unit},
+};
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Number(f64),
     Boolean(bool),
+    /// A number measured in a unit, e.g. `3 m/s`
+    Quantity(f64, Unit),
+    String(String),
+    List(Vec<Value>),
+    Matrix(Matrix),
+    /// Quoted code, e.g. `{ 2 * }`
+    Block(Vec<RpnItem>),
+    /// Every number between the bounds, e.g. `[1.9 .. 2.1]`
+    Interval(f64, f64),
+    /// A mean and its standard deviation, e.g. `9.81 +/- 0.02`
+    Measurement(f64, f64),
+    /// An instant, e.g. `2026-10-17T09:30:00Z`
+    DateTime(DateTime),
+    /// A length of time in seconds, e.g. `PT3H30M`
+    Duration(f64),
+    /// An error caught by `try`, with its kind and message, e.g.
+    /// `invalid type` and `+`
+    Error(String, String),
+    /// An exact integer, which numbers are with a word size, so that 64-bit
+    /// words don't lose bits to rounding
+    Integer(i128),
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, &|num| num.to_string(), &|n| n.to_string(), false)
+    }
+}
+
+impl Value {
+    /// Writes the value like `Display`, with `number` and `integer` writing the
+    /// numbers in it, so they can be shown differently (see
+    /// [`crate::format::Format`]). If `commas`, the numbers can have commas in
+    /// them, so list elements are separated by `;` instead
+    pub fn write(
+        &self,
+        f: &mut impl Write,
+        number: &dyn Fn(f64) -> String,
+        integer: &dyn Fn(i128) -> String,
+        commas: bool,
+    ) -> std::fmt::Result {
         match *self {
-            Value::Number(num) => write!(f, "{}", num),
+            Value::Number(num) => write!(f, "{}", number(num)),
+            Value::Integer(n) => write!(f, "{}", integer(n)),
             Value::Boolean(b) => write!(f, "{}", b),
+            Value::Quantity(num, ref unit) => write!(f, "{} {}", number(num), unit),
+            Value::String(ref s) => write!(f, "{}", s),
+            Value::List(ref list) => {
+                write!(f, "[")?;
+                for (i, x) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{} ", if commas { ';' } else { ',' })?;
+                    }
+                    match x {
+                        Value::String(s) => write!(f, "{:?}", s)?,
+                        x => x.write(f, number, integer, commas)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Matrix(ref m) => m.write(f, number, commas),
+            Value::Block(ref items) => {
+                write!(f, "{{")?;
+                for item in items {
+                    write!(f, " {}", item)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Interval(lo, hi) => write!(f, "[{} .. {}]", number(lo), number(hi)),
+            Value::Measurement(mean, sigma) => {
+                write!(f, "{} +/- {}", number(mean), number(sigma))
+            }
+            Value::DateTime(datetime) => write!(f, "{}", datetime),
+            Value::Duration(seconds) => write!(f, "{}", datetime::format_duration(seconds)),
+            Value::Error(ref kind, ref message) => write!(f, "error({}: {})", kind, message),
+        }
+    }
+
+    /// Whether any number in the value satisfies `f`, like an element of a
+    /// list or a bound of an interval
+    pub fn any_number(&self, f: &impl Fn(f64) -> bool) -> bool {
+        match *self {
+            Value::Number(num) | Value::Quantity(num, _) => f(num),
+            Value::Integer(n) => f(n as f64),
+            Value::List(ref list) => list.iter().any(|x| x.any_number(f)),
+            Value::Matrix(ref m) => {
+                (0..m.rows).any(|row| (0..m.cols).any(|col| f(m.get(row, col))))
+            }
+            Value::Interval(a, b) | Value::Measurement(a, b) => f(a) || f(b),
+            _ => false,
+        }
+    }
+
+    /// Whether the value is or has a zero that can't be divided by, like an
+    /// element of a list or an interval around zero
+    fn has_zero(&self) -> bool {
+        match *self {
+            Value::Interval(lo, hi) => lo <= 0.0 && 0.0 <= hi,
+            Value::Measurement(mean, _) => mean == 0.0,
+            Value::List(ref list) => list.iter().any(Value::has_zero),
+            _ => self.any_number(&|x| x == 0.0),
+        }
+    }
+
+    /// An integer as a float, for operators that don't work on integers
+    fn into_float(self) -> Value {
+        match self {
+            Value::Integer(n) => Value::Number(n as f64),
+            x => x,
+        }
+    }
+
+    /// Makes an error message like `invalid type: +` a value, with what's
+    /// before the `: ` as its kind, or `error` if there isn't one
+    pub fn error(message: &str) -> Value {
+        match message.split_once(": ") {
+            Some((kind, message)) => Value::Error(kind.into(), message.into()),
+            None => Value::Error("error".into(), message.into()),
+        }
+    }
+
+    /// Creates a quantity, collapsing it to a plain number if the unit is
+    /// dimensionless (e.g. `km/m`)
+    pub fn quantity(num: f64, unit: Unit) -> Value {
+        if unit.is_dimensionless() {
+            Value::Number(num * unit.factor)
+        } else {
+            Value::Quantity(num, unit)
+        }
+    }
+
+    /// Multiplies the value by a unit, e.g. `3` and `m` gives `3 m`
+    pub fn with_unit(self, unit: &Unit) -> Result<Value, String> {
+        match self {
+            Value::Number(num) => Ok(Value::quantity(num, unit.clone())),
+            Value::Quantity(num, u) => Ok(Value::quantity(
+                num,
+                (&u * unit).ok_or("overflow: unit exponent")?,
+            )),
+            _ => Err(format!("invalid type: {}", unit)),
+        }
+    }
+
+    /// The bounds of an interval, or of a number as an interval
+    fn bounds(&self) -> Option<Bounds> {
+        match *self {
+            Value::Number(num) => Some((num, num)),
+            Value::Interval(lo, hi) => Some((lo, hi)),
+            _ => None,
+        }
+    }
+
+    /// The mean and standard deviation of a measurement, or of a number as
+    /// an exact measurement
+    fn measurement(&self) -> Option<(f64, f64)> {
+        match *self {
+            Value::Number(num) => Some((num, 0.0)),
+            Value::Measurement(mean, sigma) => Some((mean, sigma)),
+            _ => None,
+        }
+    }
+
+    /// Converts the value to the given unit of the same dimension
+    pub fn convert_to(self, unit: &Unit) -> Result<Value, String> {
+        let converted = match self {
+            Value::Number(num) => Unit::one().convert(num, unit),
+            Value::Quantity(num, ref u) => u.convert(num, unit),
+            Value::Duration(seconds) => Unit::base(Dimension::TIME).convert(seconds, unit),
+            _ => None,
+        };
+        match converted {
+            Some(num) => Ok(Value::Quantity(num, unit.clone())),
+            None => Err(format!("invalid type: in {}", unit)),
         }
     }
 }
@@ -21,6 +200,41 @@ pub enum Associativity {
     Right,
 }
 
+/// Like [`MathOperator::check_finite`], for giving a value a unit or
+/// converting it, which is written as `unit`, like `km` or `in m`
+pub fn check_finite_unit(x: &Value, result: &Value, unit: &str) -> Result<(), String> {
+    let non_finite = |x: &Value| x.any_number(&|x| !x.is_finite());
+    if !non_finite(result) || non_finite(x) {
+        return Ok(());
+    }
+    Err(format!("overflow: {} {}", format_operand(x), unit))
+}
+
+/// Shows an operand in an error, with significant digits, so `1e308` isn't
+/// written out in full
+fn format_operand(x: &Value) -> String {
+    let format = Format {
+        digits: Some(15),
+        ..Default::default()
+    };
+    format.format(x)
+}
+
+/// The value of a named constant like `pi`
+pub fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
+
+/// Precedence of functions like `sqrt` in infix
+pub const FUNCTION_PRECEDENCE: u8 = 8;
+
+/// Longest list `range` will create
+pub const MAX_RANGE: usize = 1_000_000;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MathOperator {
     Add,
@@ -38,6 +252,47 @@ pub enum MathOperator {
     And,
     Or,
     Not,
+    Concat,
+    /// `++` as a function, `concat(x, y)`, which binds like other functions
+    ConcatFn,
+    Len,
+    Upper,
+    Lower,
+    Substr,
+    Str,
+    Num,
+    Nth,
+    Range,
+    Sort,
+    Reverse,
+    Transpose,
+    Det,
+    Inv,
+    Identity,
+    Solve,
+    Dot,
+    Cross,
+    Interval,
+    PlusMinus,
+    Uncertainty,
+    Weekday,
+    Format,
+    Tz,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    Shl,
+    Shr,
+    Popcount,
+    Clz,
+    Ctz,
+    Factorial,
+    Percent,
+    Square,
+    Cube,
+    ErrKind,
+    ErrMsg,
 }
 
 impl FromStr for MathOperator {
@@ -60,18 +315,131 @@ impl FromStr for MathOperator {
             "&" => Ok(MathOperator::And),
             "|" => Ok(MathOperator::Or),
             "!" => Ok(MathOperator::Not),
+            "++" => Ok(MathOperator::Concat),
+            "len" => Ok(MathOperator::Len),
+            "upper" => Ok(MathOperator::Upper),
+            "lower" => Ok(MathOperator::Lower),
+            "substr" => Ok(MathOperator::Substr),
+            "str" => Ok(MathOperator::Str),
+            "num" => Ok(MathOperator::Num),
+            "nth" => Ok(MathOperator::Nth),
+            "concat" => Ok(MathOperator::ConcatFn),
+            "range" => Ok(MathOperator::Range),
+            "sort" => Ok(MathOperator::Sort),
+            "reverse" => Ok(MathOperator::Reverse),
+            "transpose" => Ok(MathOperator::Transpose),
+            "det" => Ok(MathOperator::Det),
+            "inv" => Ok(MathOperator::Inv),
+            "identity" => Ok(MathOperator::Identity),
+            "solve" => Ok(MathOperator::Solve),
+            "dot" => Ok(MathOperator::Dot),
+            "cross" => Ok(MathOperator::Cross),
+            ".." => Ok(MathOperator::Interval),
+            "±" => Ok(MathOperator::PlusMinus),
+            "+/-" => Ok(MathOperator::Uncertainty),
+            "weekday" => Ok(MathOperator::Weekday),
+            "format" => Ok(MathOperator::Format),
+            "tz" => Ok(MathOperator::Tz),
+            "and" => Ok(MathOperator::BitAnd),
+            "or" => Ok(MathOperator::BitOr),
+            "xor" => Ok(MathOperator::BitXor),
+            "not" => Ok(MathOperator::BitNot),
+            "shl" => Ok(MathOperator::Shl),
+            "shr" => Ok(MathOperator::Shr),
+            "popcount" => Ok(MathOperator::Popcount),
+            "clz" => Ok(MathOperator::Clz),
+            "ctz" => Ok(MathOperator::Ctz),
+            "fact" => Ok(MathOperator::Factorial),
+            "%" => Ok(MathOperator::Percent),
+            "²" => Ok(MathOperator::Square),
+            "³" => Ok(MathOperator::Cube),
+            "errkind" => Ok(MathOperator::ErrKind),
+            "errmsg" => Ok(MathOperator::ErrMsg),
             _ => Err(()),
         }
     }
 }
 
+impl Display for MathOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            MathOperator::Add => "+",
+            MathOperator::Sub => "-",
+            MathOperator::Mul => "*",
+            MathOperator::Div => "/",
+            MathOperator::Pow => "^",
+            MathOperator::Sqrt => "sqrt",
+            MathOperator::Eq => "=",
+            MathOperator::Ne => "!=",
+            MathOperator::Gt => ">",
+            MathOperator::Ge => ">=",
+            MathOperator::Lt => "<",
+            MathOperator::Le => "<=",
+            MathOperator::And => "&",
+            MathOperator::Or => "|",
+            MathOperator::Not => "!",
+            MathOperator::Concat => "++",
+            MathOperator::ConcatFn => "concat",
+            MathOperator::Len => "len",
+            MathOperator::Upper => "upper",
+            MathOperator::Lower => "lower",
+            MathOperator::Substr => "substr",
+            MathOperator::Str => "str",
+            MathOperator::Num => "num",
+            MathOperator::Nth => "nth",
+            MathOperator::Range => "range",
+            MathOperator::Sort => "sort",
+            MathOperator::Reverse => "reverse",
+            MathOperator::Transpose => "transpose",
+            MathOperator::Det => "det",
+            MathOperator::Inv => "inv",
+            MathOperator::Identity => "identity",
+            MathOperator::Solve => "solve",
+            MathOperator::Dot => "dot",
+            MathOperator::Cross => "cross",
+            MathOperator::Interval => "..",
+            MathOperator::PlusMinus => "±",
+            MathOperator::Uncertainty => "+/-",
+            MathOperator::Weekday => "weekday",
+            MathOperator::Format => "format",
+            MathOperator::Tz => "tz",
+            MathOperator::BitAnd => "and",
+            MathOperator::BitOr => "or",
+            MathOperator::BitXor => "xor",
+            MathOperator::BitNot => "not",
+            MathOperator::Shl => "shl",
+            MathOperator::Shr => "shr",
+            MathOperator::Popcount => "popcount",
+            MathOperator::Clz => "clz",
+            MathOperator::Ctz => "ctz",
+            MathOperator::Factorial => "fact",
+            MathOperator::Percent => "%",
+            MathOperator::Square => "²",
+            MathOperator::Cube => "³",
+            MathOperator::ErrKind => "errkind",
+            MathOperator::ErrMsg => "errmsg",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
 impl MathOperator {
     pub fn precedence(self) -> u8 {
-        // https://en.wikipedia.org/wiki/Order_of_operations
+        // https://en.wikipedia.org/wiki/Order_of_operations, with the bitwise
+        // operators between comparisons and arithmetic as in Python
         match self {
-            MathOperator::Pow | MathOperator::Sqrt => 4,
-            MathOperator::Mul | MathOperator::Div => 3,
-            MathOperator::Add | MathOperator::Sub => 2,
+            MathOperator::Not => 9,
+            MathOperator::Mul | MathOperator::Div => 7,
+            MathOperator::Add
+            | MathOperator::Sub
+            | MathOperator::Concat
+            | MathOperator::Interval
+            | MathOperator::PlusMinus
+            | MathOperator::Uncertainty => 6,
+            MathOperator::Shl | MathOperator::Shr => 5,
+            MathOperator::BitAnd => 4,
+            MathOperator::BitXor => 3,
+            MathOperator::BitOr => 2,
             MathOperator::Eq
             | MathOperator::Ne
             | MathOperator::Gt
@@ -79,38 +447,324 @@ impl MathOperator {
             | MathOperator::Lt
             | MathOperator::Le => 1,
             MathOperator::And | MathOperator::Or => 0,
-            MathOperator::Not => 5,
+            // functions, and `^`, which binds as tightly
+            _ => FUNCTION_PRECEDENCE,
         }
     }
 
     pub fn associativity(self) -> Associativity {
         match self {
-            MathOperator::Pow | MathOperator::Sqrt => Associativity::Right,
-            _ => Associativity::Left,
+            MathOperator::Add
+            | MathOperator::Sub
+            | MathOperator::Mul
+            | MathOperator::Div
+            | MathOperator::Eq
+            | MathOperator::Ne
+            | MathOperator::Gt
+            | MathOperator::Ge
+            | MathOperator::Lt
+            | MathOperator::Le
+            | MathOperator::And
+            | MathOperator::Or
+            | MathOperator::Not
+            | MathOperator::Concat
+            | MathOperator::Interval
+            | MathOperator::PlusMinus
+            | MathOperator::Uncertainty
+            | MathOperator::BitAnd
+            | MathOperator::BitOr
+            | MathOperator::BitXor
+            | MathOperator::Shl
+            | MathOperator::Shr => Associativity::Left,
+            _ => Associativity::Right,
         }
     }
 
-    pub fn rpn_exec(self, stack: &mut Vec<Value>) -> Result<(), String> {
+    /// Number of operands the operator takes from the stack
+    pub fn arity(self) -> usize {
         match self {
-            MathOperator::Sqrt | MathOperator::Not => self.unary_op(stack)?,
-            _ => self.binary_op(stack)?,
+            MathOperator::Sqrt
+            | MathOperator::Not
+            | MathOperator::Len
+            | MathOperator::Upper
+            | MathOperator::Lower
+            | MathOperator::Str
+            | MathOperator::Num
+            | MathOperator::Sort
+            | MathOperator::Reverse
+            | MathOperator::Transpose
+            | MathOperator::Det
+            | MathOperator::Inv
+            | MathOperator::Identity
+            | MathOperator::Weekday
+            | MathOperator::BitNot
+            | MathOperator::Popcount
+            | MathOperator::Clz
+            | MathOperator::Ctz
+            | MathOperator::Factorial
+            | MathOperator::Percent
+            | MathOperator::Square
+            | MathOperator::Cube
+            | MathOperator::ErrKind
+            | MathOperator::ErrMsg => 1,
+            MathOperator::Substr => 3,
+            _ => 2,
+        }
+    }
+
+    pub fn rpn_exec(self, stack: &mut Vec<Value>) -> Result<(), String> {
+        match self.arity() {
+            1 => self.unary_op(stack)?,
+            2 => self.binary_op(stack)?,
+            _ => self.ternary_op(stack)?,
         }
         Ok(())
     }
 
+    /// Whether the operator applies element-wise to lists
+    fn broadcasts(self) -> bool {
+        matches!(
+            self,
+            MathOperator::Add
+                | MathOperator::Sub
+                | MathOperator::Mul
+                | MathOperator::Div
+                | MathOperator::Pow
+                | MathOperator::Sqrt
+                | MathOperator::BitAnd
+                | MathOperator::BitOr
+                | MathOperator::BitXor
+                | MathOperator::BitNot
+                | MathOperator::Shl
+                | MathOperator::Shr
+                | MathOperator::Popcount
+                | MathOperator::Clz
+                | MathOperator::Ctz
+                | MathOperator::Factorial
+        )
+    }
+
+    /// Whether the operator compares its operands, which can be chained in
+    /// infix, like `1 < x < 5`
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            MathOperator::Eq
+                | MathOperator::Ne
+                | MathOperator::Gt
+                | MathOperator::Ge
+                | MathOperator::Lt
+                | MathOperator::Le
+        )
+    }
+
+    /// Whether the operator comes after its operand in infix, like `3!`
+    pub fn is_postfix(self) -> bool {
+        matches!(
+            self,
+            MathOperator::Factorial
+                | MathOperator::Percent
+                | MathOperator::Square
+                | MathOperator::Cube
+        )
+    }
+
+    /// Fails if the operator made an infinity or a NaN out of finite operands,
+    /// for strict IEEE mode, naming the operator and the operands
+    pub fn check_finite(self, operands: &[Value], result: &Value) -> Result<(), String> {
+        let non_finite = |x: &Value| x.any_number(&|x| !x.is_finite());
+        if !non_finite(result) || operands.iter().any(non_finite) {
+            return Ok(());
+        }
+        // a string like `"inf"` read with `num` is taken as written, like the
+        // literal `inf`
+        if operands.iter().any(|x| matches!(x, Value::String(_))) {
+            return Ok(());
+        }
+        let kind = match self {
+            MathOperator::Div if operands[1].has_zero() => "division by zero",
+            // `0 ^ -1` is `1 / 0`
+            MathOperator::Pow if operands[0].has_zero() && operands[1].any_number(&|y| y < 0.0) => {
+                "division by zero"
+            }
+            _ if result.any_number(&f64::is_nan) => "invalid domain",
+            _ => "overflow",
+        };
+        let operands: Vec<_> = operands.iter().map(format_operand).collect();
+        match operands[..] {
+            [ref x, ref y] => Err(format!("{}: {} {} {}", kind, x, self, y)),
+            _ => Err(format!("{}: {}({})", kind, self, operands.join(", "))),
+        }
+    }
+
     fn unary_op(self, stack: &mut Vec<Value>) -> Result<(), String> {
-        let x = stack
-            .pop()
-            .ok_or_else(|| "invalid syntax: too few operands")?;
+        let x = stack.pop().ok_or("invalid syntax: too few operands")?;
+        stack.push(self.unary(x)?);
+        Ok(())
+    }
+
+    fn unary(self, x: Value) -> Result<Value, String> {
+        let x = match x {
+            Value::List(xs) if self.broadcasts() => {
+                return xs
+                    .into_iter()
+                    .map(|x| self.unary(x))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List)
+            }
+            Value::Integer(n) if self == MathOperator::BitNot => return Ok(Value::Integer(!n)),
+            x => x.into_float(),
+        };
         let result = match self {
             MathOperator::Sqrt => match x {
                 Value::Number(num) => Value::Number(num.sqrt()),
+                Value::Interval(lo, hi) => match interval::sqrt((lo, hi)) {
+                    Some((lo, hi)) => Value::Interval(lo, hi),
+                    None => return Err("invalid domain: sqrt".into()),
+                },
+                Value::Measurement(mean, sigma) => {
+                    let root = mean.sqrt();
+                    Value::Measurement(root, sigma / (2.0 * root))
+                }
+                Value::Quantity(num, unit) => match unit.powf(0.5) {
+                    Ok(unit) => Value::quantity(num.sqrt(), unit),
+                    Err(_) => return Err("invalid type: sqrt".into()),
+                },
                 _ => return Err("invalid type: sqrt".into()),
             },
             MathOperator::Not => match x {
                 Value::Boolean(b) => Value::Boolean(!b),
                 _ => return Err("invalid type: not".into()),
             },
+            MathOperator::Len => match x {
+                Value::String(s) => Value::Number(s.chars().count() as f64),
+                Value::List(list) => Value::Number(list.len() as f64),
+                _ => return Err("invalid type: len".into()),
+            },
+            MathOperator::Upper => match x {
+                Value::String(s) => Value::String(s.to_uppercase()),
+                _ => return Err("invalid type: upper".into()),
+            },
+            MathOperator::Lower => match x {
+                Value::String(s) => Value::String(s.to_lowercase()),
+                _ => return Err("invalid type: lower".into()),
+            },
+            MathOperator::Str => Value::String(x.to_string()),
+            MathOperator::Num => match x {
+                Value::String(s) => match s.trim().parse::<f64>() {
+                    Ok(num) => Value::Number(num),
+                    Err(_) => return Err(format!("invalid number: {:?}", s)),
+                },
+                Value::Number(num) => Value::Number(num),
+                _ => return Err("invalid type: num".into()),
+            },
+            MathOperator::Sort => match x {
+                Value::List(mut list) => {
+                    let mut error = false;
+                    list.sort_by(|x, y| {
+                        compare(x, y).unwrap_or_else(|| {
+                            error = true;
+                            Ordering::Equal
+                        })
+                    });
+                    if error {
+                        return Err("invalid type: sort".into());
+                    }
+                    Value::List(list)
+                }
+                _ => return Err("invalid type: sort".into()),
+            },
+            MathOperator::Reverse => match x {
+                Value::List(list) => Value::List(list.into_iter().rev().collect()),
+                Value::String(s) => Value::String(s.chars().rev().collect()),
+                _ => return Err("invalid type: reverse".into()),
+            },
+            MathOperator::Transpose => match x {
+                Value::Matrix(m) => Value::Matrix(m.transpose()),
+                _ => return Err("invalid type: transpose".into()),
+            },
+            MathOperator::Det => match x {
+                Value::Matrix(m) => match m.det() {
+                    Some(det) => Value::Number(det),
+                    None => return Err("invalid shape: det".into()),
+                },
+                _ => return Err("invalid type: det".into()),
+            },
+            MathOperator::Inv => match x {
+                Value::Matrix(m) if !m.is_square() => return Err("invalid shape: inv".into()),
+                Value::Matrix(m) => match m.inverse() {
+                    Some(inv) => Value::Matrix(inv),
+                    None => return Err("singular matrix: inv".into()),
+                },
+                _ => return Err("invalid type: inv".into()),
+            },
+            MathOperator::Identity => match x {
+                Value::Number(n) if n >= 1.0 && n.fract() == 0.0 => {
+                    if n > MAX_SIZE as f64 {
+                        return Err("invalid size: identity".into());
+                    }
+                    Value::Matrix(Matrix::identity(n as usize))
+                }
+                _ => return Err("invalid type: identity".into()),
+            },
+            MathOperator::BitNot
+            | MathOperator::Popcount
+            | MathOperator::Clz
+            | MathOperator::Ctz => {
+                let x = integer(&x, self)?;
+                Value::Number(match self {
+                    MathOperator::BitNot => !x as f64,
+                    MathOperator::Popcount => x.count_ones() as f64,
+                    MathOperator::Clz => x.leading_zeros() as f64,
+                    _ => x.trailing_zeros() as f64,
+                })
+            }
+            MathOperator::Weekday => match x {
+                Value::DateTime(datetime) => Value::String(datetime.weekday().into()),
+                _ => return Err("invalid type: weekday".into()),
+            },
+            MathOperator::Factorial => match x {
+                Value::Number(n) => Value::Number(factorial(n)),
+                Value::Interval(..) => return Err("unsupported for interval: fact".into()),
+                Value::Measurement(..) => return Err("unsupported for measurement: fact".into()),
+                _ => return Err("invalid type: fact".into()),
+            },
+            // these work on anything that can be divided or raised to a power
+            MathOperator::Percent => MathOperator::Div.binary(x, Value::Number(100.0))?,
+            MathOperator::Square => MathOperator::Pow.binary(x, Value::Number(2.0))?,
+            MathOperator::Cube => MathOperator::Pow.binary(x, Value::Number(3.0))?,
+            MathOperator::ErrKind => match x {
+                Value::Error(kind, _) => Value::String(kind),
+                _ => return Err("invalid type: errkind".into()),
+            },
+            MathOperator::ErrMsg => match x {
+                Value::Error(_, message) => Value::String(message),
+                _ => return Err("invalid type: errmsg".into()),
+            },
+            _ => unreachable!(),
+        };
+        Ok(result)
+    }
+
+    fn ternary_op(self, stack: &mut Vec<Value>) -> Result<(), String> {
+        let z = stack.pop().ok_or("invalid syntax: too few operands")?;
+        let y = stack.pop().ok_or("invalid syntax: too few operands")?;
+        let x = stack.pop().ok_or("invalid syntax: too few operands")?;
+        let (x, y, z) = (x.into_float(), y.into_float(), z.into_float());
+        let result = match self {
+            MathOperator::Substr => match (x, y, z) {
+                (Value::String(s), Value::Number(start), Value::Number(count))
+                    if start >= 0.0 && count >= 0.0 =>
+                {
+                    Value::String(
+                        s.chars()
+                            .skip(start as usize)
+                            .take(count as usize)
+                            .collect(),
+                    )
+                }
+                _ => return Err("invalid type: substr".into()),
+            },
             _ => unreachable!(),
         };
         stack.push(result);
@@ -118,58 +772,201 @@ impl MathOperator {
     }
 
     fn binary_op(self, stack: &mut Vec<Value>) -> Result<(), String> {
-        let y = stack
-            .pop()
-            .ok_or_else(|| "invalid syntax: too few operands")?;
-        let x = stack
-            .pop()
-            .ok_or_else(|| "invalid syntax: too few operands")?;
+        let y = stack.pop().ok_or("invalid syntax: too few operands")?;
+        let x = stack.pop().ok_or("invalid syntax: too few operands")?;
+        stack.push(self.binary(x, y)?);
+        Ok(())
+    }
+
+    fn binary(self, x: Value, y: Value) -> Result<Value, String> {
+        let (x, y) = match (x, y) {
+            (Value::List(xs), Value::List(ys)) if self.broadcasts() => {
+                if xs.len() != ys.len() {
+                    return Err(format!("invalid length: {}", self));
+                }
+                return xs
+                    .into_iter()
+                    .zip(ys)
+                    .map(|(x, y)| self.binary(x, y))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List);
+            }
+            // lists multiplied by matrices are vectors, not lists of numbers
+            (Value::List(xs), y) if self.broadcasts() && !matches!(y, Value::Matrix(_)) => {
+                return xs
+                    .into_iter()
+                    .map(|x| self.binary(x, y.clone()))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List);
+            }
+            (x, Value::List(ys)) if self.broadcasts() && !matches!(x, Value::Matrix(_)) => {
+                return ys
+                    .into_iter()
+                    .map(|y| self.binary(x.clone(), y))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List);
+            }
+            (Value::Integer(x), Value::Integer(y)) => {
+                if let Some(result) = self.integer_binary(x, y) {
+                    return result;
+                }
+                (Value::Number(x as f64), Value::Number(y as f64))
+            }
+            (x, y) => (x.into_float(), y.into_float()),
+        };
+        if matches!(x, Value::Interval(..)) || matches!(y, Value::Interval(..)) {
+            if let (Some(x), Some(y)) = (x.bounds(), y.bounds()) {
+                return self.interval_binary(x, y);
+            }
+            // bounds aren't kept through units
+            if matches!(x, Value::Quantity(..)) || matches!(y, Value::Quantity(..)) {
+                return Err(format!("unsupported for interval: {}", self));
+            }
+        }
+        if matches!(x, Value::DateTime(_) | Value::Duration(_))
+            || matches!(y, Value::DateTime(_) | Value::Duration(_))
+        {
+            return self.datetime_binary(x, y);
+        }
+        if matches!(x, Value::Measurement(..)) || matches!(y, Value::Measurement(..)) {
+            if let (Some(x), Some(y)) = (x.measurement(), y.measurement()) {
+                return self.measurement_binary(x, y);
+            }
+            // errors aren't propagated through units
+            if matches!(x, Value::Quantity(..)) || matches!(y, Value::Quantity(..)) {
+                return Err(format!("unsupported for measurement: {}", self));
+            }
+        }
         let result = match self {
             MathOperator::Add => match (x, y) {
                 (Value::Number(x), Value::Number(y)) => Value::Number(x + y),
+                (Value::Quantity(x, u), Value::Quantity(y, v)) => match v.convert(y, &u) {
+                    Some(y) => Value::Quantity(x + y, u),
+                    None => return Err("invalid type: +".into()),
+                },
+                (Value::Matrix(x), Value::Matrix(y)) => match x.zip_with(&y, |x, y| x + y) {
+                    Some(m) => Value::Matrix(m),
+                    None => return Err("invalid shape: +".into()),
+                },
                 _ => return Err("invalid type: +".into()),
             },
             MathOperator::Sub => match (x, y) {
                 (Value::Number(x), Value::Number(y)) => Value::Number(x - y),
+                (Value::Quantity(x, u), Value::Quantity(y, v)) => match v.convert(y, &u) {
+                    Some(y) => Value::Quantity(x - y, u),
+                    None => return Err("invalid type: -".into()),
+                },
+                (Value::Matrix(x), Value::Matrix(y)) => match x.zip_with(&y, |x, y| x - y) {
+                    Some(m) => Value::Matrix(m),
+                    None => return Err("invalid shape: -".into()),
+                },
                 _ => return Err("invalid type: -".into()),
             },
             MathOperator::Mul => match (x, y) {
                 (Value::Number(x), Value::Number(y)) => Value::Number(x * y),
+                (Value::Number(x), Value::Quantity(y, u))
+                | (Value::Quantity(y, u), Value::Number(x)) => Value::Quantity(x * y, u),
+                (Value::Quantity(x, u), Value::Quantity(y, v)) => {
+                    Value::quantity(x * y, (&u * &v).ok_or("overflow: unit exponent")?)
+                }
+                (Value::Number(x), Value::Matrix(m)) | (Value::Matrix(m), Value::Number(x)) => {
+                    Value::Matrix(m.map(|y| x * y))
+                }
+                (Value::Matrix(x), Value::Matrix(y)) => match x.matmul(&y) {
+                    Some(m) => Value::Matrix(m),
+                    None => return Err("invalid shape: *".into()),
+                },
+                (Value::Matrix(m), Value::List(v)) => {
+                    let v = numbers(&v).ok_or("invalid type: *")?;
+                    match m.matmul(&Matrix::column(v)) {
+                        Some(v) => number_list(v.data),
+                        None => return Err("invalid shape: *".into()),
+                    }
+                }
                 _ => return Err("invalid type: *".into()),
             },
             MathOperator::Div => match (x, y) {
                 (Value::Number(x), Value::Number(y)) => Value::Number(x / y),
+                (Value::Quantity(x, u), Value::Number(y)) => Value::Quantity(x / y, u),
+                (Value::Number(x), Value::Quantity(y, u)) => {
+                    Value::Quantity(x / y, u.powi(-1).ok_or("overflow: unit exponent")?)
+                }
+                (Value::Quantity(x, u), Value::Quantity(y, v)) => {
+                    Value::quantity(x / y, (&u / &v).ok_or("overflow: unit exponent")?)
+                }
+                (Value::Matrix(m), Value::Number(y)) => Value::Matrix(m.map(|x| x / y)),
                 _ => return Err("invalid type: /".into()),
             },
             MathOperator::Pow => match (x, y) {
                 (Value::Number(x), Value::Number(y)) => Value::Number(x.powf(y)),
+                (Value::Quantity(x, u), Value::Number(y)) => Value::quantity(x.powf(y), u.powf(y)?),
+                (Value::Matrix(m), Value::Number(y)) if y.fract() == 0.0 => {
+                    if y < i32::MIN as f64 || y > i32::MAX as f64 {
+                        return Err("invalid size: ^".into());
+                    }
+                    match m.powi(y as i32) {
+                        Some(m) => Value::Matrix(m),
+                        None if !m.is_square() => return Err("invalid shape: ^".into()),
+                        None => return Err("singular matrix: ^".into()),
+                    }
+                }
                 _ => return Err("invalid type: ^".into()),
             },
             MathOperator::Eq => match (x, y) {
                 (Value::Number(x), Value::Number(y)) => Value::Boolean(x == y),
                 (Value::Boolean(x), Value::Boolean(y)) => Value::Boolean(x == y),
+                (Value::String(x), Value::String(y)) => Value::Boolean(x == y),
+                (x @ Value::List(_), y @ Value::List(_)) => Value::Boolean(x == y),
+                (Value::Matrix(x), Value::Matrix(y)) => Value::Boolean(x == y),
+                (x @ Value::Quantity(..), y @ Value::Quantity(..)) => {
+                    match compare_quantities(&x, &y) {
+                        Some((x, y)) => Value::Boolean(x == y),
+                        None => return Err("invalid type: =".into()),
+                    }
+                }
                 _ => return Err("invalid type: =".into()),
             },
             MathOperator::Ne => match (x, y) {
                 (Value::Number(x), Value::Number(y)) => Value::Boolean(x != y),
                 (Value::Boolean(x), Value::Boolean(y)) => Value::Boolean(x != y),
+                (Value::String(x), Value::String(y)) => Value::Boolean(x != y),
+                (x @ Value::List(_), y @ Value::List(_)) => Value::Boolean(x != y),
+                (Value::Matrix(x), Value::Matrix(y)) => Value::Boolean(x != y),
+                (x @ Value::Quantity(..), y @ Value::Quantity(..)) => {
+                    match compare_quantities(&x, &y) {
+                        Some((x, y)) => Value::Boolean(x != y),
+                        None => return Err("invalid type: !=".into()),
+                    }
+                }
                 _ => return Err("invalid type: !=".into()),
             },
             MathOperator::Gt => match (x, y) {
-                (Value::Number(x), Value::Number(y)) => Value::Boolean(x > y),
-                _ => return Err("invalid type: >".into()),
+                (Value::String(x), Value::String(y)) => Value::Boolean(x > y),
+                (x, y) => match compare_quantities(&x, &y) {
+                    Some((x, y)) => Value::Boolean(x > y),
+                    None => return Err("invalid type: >".into()),
+                },
             },
             MathOperator::Ge => match (x, y) {
-                (Value::Number(x), Value::Number(y)) => Value::Boolean(x >= y),
-                _ => return Err("invalid type: >=".into()),
+                (Value::String(x), Value::String(y)) => Value::Boolean(x >= y),
+                (x, y) => match compare_quantities(&x, &y) {
+                    Some((x, y)) => Value::Boolean(x >= y),
+                    None => return Err("invalid type: >=".into()),
+                },
             },
             MathOperator::Lt => match (x, y) {
-                (Value::Number(x), Value::Number(y)) => Value::Boolean(x < y),
-                _ => return Err("invalid type: <".into()),
+                (Value::String(x), Value::String(y)) => Value::Boolean(x < y),
+                (x, y) => match compare_quantities(&x, &y) {
+                    Some((x, y)) => Value::Boolean(x < y),
+                    None => return Err("invalid type: <".into()),
+                },
             },
             MathOperator::Le => match (x, y) {
-                (Value::Number(x), Value::Number(y)) => Value::Boolean(x <= y),
-                _ => return Err("invalid type: <=".into()),
+                (Value::String(x), Value::String(y)) => Value::Boolean(x <= y),
+                (x, y) => match compare_quantities(&x, &y) {
+                    Some((x, y)) => Value::Boolean(x <= y),
+                    None => return Err("invalid type: <=".into()),
+                },
             },
             MathOperator::And => match (x, y) {
                 (Value::Boolean(x), Value::Boolean(y)) => Value::Boolean(x && y),
@@ -179,10 +976,419 @@ impl MathOperator {
                 (Value::Boolean(x), Value::Boolean(y)) => Value::Boolean(x || y),
                 _ => return Err("invalid type: ||".into()),
             },
+            MathOperator::Concat | MathOperator::ConcatFn => match (x, y) {
+                (Value::String(x), Value::String(y)) => Value::String(x + &y),
+                (Value::List(mut x), Value::List(y)) => {
+                    x.extend(y);
+                    Value::List(x)
+                }
+                _ => return Err(format!("invalid type: {}", self)),
+            },
+            MathOperator::Nth => match (x, y) {
+                (Value::List(mut list), Value::Number(i)) if i.fract() == 0.0 => {
+                    // negative indices count from the end
+                    let i = if i < 0.0 { i + list.len() as f64 } else { i };
+                    if i < 0.0 || i >= list.len() as f64 {
+                        return Err("invalid index: nth".into());
+                    }
+                    list.swap_remove(i as usize)
+                }
+                _ => return Err("invalid type: nth".into()),
+            },
+            MathOperator::Range => match (x, y) {
+                (Value::Number(start), Value::Number(end)) => {
+                    let len = (end - start).ceil().max(0.0);
+                    if !len.is_finite() || len > MAX_RANGE as f64 {
+                        return Err("invalid size: range".into());
+                    }
+                    let len = len as usize;
+                    Value::List((0..len).map(|i| Value::Number(start + i as f64)).collect())
+                }
+                _ => return Err("invalid type: range".into()),
+            },
+            MathOperator::Solve => match (x, y) {
+                (Value::Matrix(a), b @ (Value::List(_) | Value::Matrix(_))) => {
+                    let (b, is_list) = match b {
+                        Value::List(b) => (
+                            Matrix::column(numbers(&b).ok_or("invalid type: solve")?),
+                            true,
+                        ),
+                        Value::Matrix(b) => (b, false),
+                        _ => unreachable!(),
+                    };
+                    match a.solve(&b) {
+                        Ok(x) if is_list => number_list(x.data),
+                        Ok(x) => Value::Matrix(x),
+                        Err(MatrixError::Shape) => return Err("invalid shape: solve".into()),
+                        Err(MatrixError::Singular) => return Err("singular matrix: solve".into()),
+                    }
+                }
+                _ => return Err("invalid type: solve".into()),
+            },
+            MathOperator::Dot => match (x, y) {
+                (Value::List(x), Value::List(y)) => {
+                    let x = numbers(&x).ok_or("invalid type: dot")?;
+                    let y = numbers(&y).ok_or("invalid type: dot")?;
+                    if x.len() != y.len() {
+                        return Err("invalid shape: dot".into());
+                    }
+                    Value::Number(x.iter().zip(y).map(|(x, y)| x * y).sum())
+                }
+                _ => return Err("invalid type: dot".into()),
+            },
+            MathOperator::Interval => match (x, y) {
+                (Value::Number(lo), Value::Number(hi)) if lo <= hi => Value::Interval(lo, hi),
+                (Value::Quantity(..), _) | (_, Value::Quantity(..)) => {
+                    return Err("unsupported for interval: ..".into())
+                }
+                _ => return Err("invalid type: ..".into()),
+            },
+            MathOperator::PlusMinus => match (x, y) {
+                (Value::Number(x), Value::Number(r)) => {
+                    let (lo, hi) = interval::plus_minus(x, r);
+                    Value::Interval(lo, hi)
+                }
+                (Value::Quantity(..), _) | (_, Value::Quantity(..)) => {
+                    return Err("unsupported for interval: ±".into())
+                }
+                _ => return Err("invalid type: ±".into()),
+            },
+            MathOperator::Uncertainty => match (x, y) {
+                (Value::Number(mean), Value::Number(sigma)) => {
+                    Value::Measurement(mean, sigma.abs())
+                }
+                (Value::Quantity(..), _) | (_, Value::Quantity(..)) => {
+                    return Err("unsupported for measurement: +/-".into())
+                }
+                _ => return Err("invalid type: +/-".into()),
+            },
+            MathOperator::BitAnd
+            | MathOperator::BitOr
+            | MathOperator::BitXor
+            | MathOperator::Shl
+            | MathOperator::Shr => {
+                let x = integer(&x, self)?;
+                let y = integer(&y, self)?;
+                Value::Number(match self {
+                    MathOperator::BitAnd => x & y,
+                    MathOperator::BitOr => x | y,
+                    MathOperator::BitXor => x ^ y,
+                    // shifting by the word size or more shifts every bit out
+                    MathOperator::Shl => x.checked_shl(shift(y, self)?).unwrap_or(0),
+                    _ => x.checked_shr(shift(y, self)?).unwrap_or(x >> 63),
+                } as f64)
+            }
+            MathOperator::Cross => match (x, y) {
+                (Value::List(x), Value::List(y)) => {
+                    let x = numbers(&x).ok_or("invalid type: cross")?;
+                    let y = numbers(&y).ok_or("invalid type: cross")?;
+                    match (&x[..], &y[..]) {
+                        (&[x1, x2, x3], &[y1, y2, y3]) => number_list(vec![
+                            x2 * y3 - x3 * y2,
+                            x3 * y1 - x1 * y3,
+                            x1 * y2 - x2 * y1,
+                        ]),
+                        _ => return Err("invalid shape: cross".into()),
+                    }
+                }
+                _ => return Err("invalid type: cross".into()),
+            },
             _ => unreachable!(),
         };
-        stack.push(result);
-        Ok(())
+        Ok(result)
+    }
+
+    /// Applies the operator to intervals (or numbers and intervals), giving an
+    /// error for comparisons that are true for some values and false for others
+    fn interval_binary(self, x: Bounds, y: Bounds) -> Result<Value, String> {
+        let interval = |(lo, hi): Bounds| Value::Interval(lo, hi);
+        let compared = match self {
+            MathOperator::Add => return Ok(interval(interval::add(x, y))),
+            MathOperator::Sub => return Ok(interval(interval::sub(x, y))),
+            MathOperator::Mul => return Ok(interval(interval::mul(x, y))),
+            MathOperator::Div => {
+                return interval::div(x, y)
+                    .map(interval)
+                    .ok_or_else(|| "division by zero: /".into())
+            }
+            MathOperator::Pow => {
+                return interval::pow(x, y)
+                    .map(interval)
+                    .ok_or_else(|| "invalid domain: ^".into())
+            }
+            MathOperator::Eq => interval::equal(x, y),
+            MathOperator::Ne => interval::equal(x, y).map(|b| !b),
+            MathOperator::Lt => interval::less(x, y, false),
+            MathOperator::Le => interval::less(x, y, true),
+            MathOperator::Gt => interval::less(y, x, false),
+            MathOperator::Ge => interval::less(y, x, true),
+            _ => return Err(format!("invalid type: {}", self)),
+        };
+        compared
+            .map(Value::Boolean)
+            .ok_or_else(|| format!("ambiguous comparison: {}", self))
+    }
+
+    /// Applies the operator to exact integers, or gives `None` for operators
+    /// that need floats, like `sqrt`. Results wrap around in 128 bits, for the
+    /// interpreter to wrap to its word size.
+    fn integer_binary(self, x: i128, y: i128) -> Option<Result<Value, String>> {
+        let result = match self {
+            MathOperator::Add => Value::Integer(x.wrapping_add(y)),
+            MathOperator::Sub => Value::Integer(x.wrapping_sub(y)),
+            MathOperator::Mul => Value::Integer(x.wrapping_mul(y)),
+            MathOperator::Div if y == 0 => {
+                return Some(Err(format!("division by zero: {} / {}", x, y)))
+            }
+            MathOperator::Div => Value::Integer(x.wrapping_div(y)),
+            MathOperator::Pow if y >= 0 => Value::Integer(wrapping_pow(x, y as u128)),
+            MathOperator::Eq => Value::Boolean(x == y),
+            MathOperator::Ne => Value::Boolean(x != y),
+            MathOperator::Gt => Value::Boolean(x > y),
+            MathOperator::Ge => Value::Boolean(x >= y),
+            MathOperator::Lt => Value::Boolean(x < y),
+            MathOperator::Le => Value::Boolean(x <= y),
+            MathOperator::BitAnd => Value::Integer(x & y),
+            MathOperator::BitOr => Value::Integer(x | y),
+            MathOperator::BitXor => Value::Integer(x ^ y),
+            MathOperator::Shl | MathOperator::Shr => {
+                let amount = match u32::try_from(y) {
+                    Ok(amount) => amount,
+                    Err(_) => return Some(Err(format!("invalid shift: {}", self))),
+                };
+                // shifting by 128 or more shifts every bit out
+                Value::Integer(match self {
+                    MathOperator::Shl => x.checked_shl(amount).unwrap_or(0),
+                    _ => x.checked_shr(amount).unwrap_or(x >> 127),
+                })
+            }
+            _ => return None,
+        };
+        Some(Ok(result))
+    }
+
+    /// Applies the operator to dates and durations, where durations can be
+    /// mixed with quantities of time
+    fn datetime_binary(self, x: Value, y: Value) -> Result<Value, String> {
+        let invalid = || format!("invalid type: {}", self);
+        let out_of_range = || format!("out of range: {}", self);
+        // durations are at most as long as the span of dates
+        let duration = |seconds: f64| match seconds.abs() <= datetime::MAX_DURATION {
+            true => Ok(Value::Duration(seconds)),
+            false => Err(out_of_range()),
+        };
+        let result = match (self, x, y) {
+            (MathOperator::Add, Value::DateTime(t), d)
+            | (MathOperator::Add, d, Value::DateTime(t)) => {
+                let d = seconds(&d).ok_or_else(invalid)?;
+                Value::DateTime(t.plus(d).ok_or_else(out_of_range)?)
+            }
+            (MathOperator::Sub, Value::DateTime(t), Value::DateTime(u)) => {
+                Value::Duration(t.seconds - u.seconds)
+            }
+            (MathOperator::Sub, Value::DateTime(t), d) => {
+                let d = seconds(&d).ok_or_else(invalid)?;
+                Value::DateTime(t.plus(-d).ok_or_else(out_of_range)?)
+            }
+            (MathOperator::Add | MathOperator::Sub, x, y) => {
+                let (x, y) = (seconds(&x), seconds(&y));
+                let (x, y) = x.zip(y).ok_or_else(invalid)?;
+                match self {
+                    MathOperator::Add => duration(x + y)?,
+                    _ => duration(x - y)?,
+                }
+            }
+            (MathOperator::Mul, Value::Duration(d), Value::Number(n))
+            | (MathOperator::Mul, Value::Number(n), Value::Duration(d)) => duration(d * n)?,
+            (MathOperator::Div, Value::Duration(d), Value::Number(n)) => duration(d / n)?,
+            (MathOperator::Div, x, y) => {
+                let (x, y) = seconds(&x).zip(seconds(&y)).ok_or_else(invalid)?;
+                Value::Number(x / y)
+            }
+            (
+                op @ (MathOperator::Eq
+                | MathOperator::Ne
+                | MathOperator::Gt
+                | MathOperator::Ge
+                | MathOperator::Lt
+                | MathOperator::Le),
+                x,
+                y,
+            ) => {
+                let (x, y) = compare_quantities(&x, &y).ok_or_else(invalid)?;
+                Value::Boolean(match op {
+                    MathOperator::Eq => x == y,
+                    MathOperator::Ne => x != y,
+                    MathOperator::Gt => x > y,
+                    MathOperator::Ge => x >= y,
+                    MathOperator::Lt => x < y,
+                    _ => x <= y,
+                })
+            }
+            (MathOperator::Format, Value::DateTime(t), Value::String(format)) => {
+                Value::String(t.format(&format)?)
+            }
+            (MathOperator::Tz, Value::DateTime(t), Value::String(offset)) => {
+                match datetime::parse_offset(&offset) {
+                    Some(offset) => Value::DateTime(DateTime { offset, ..t }),
+                    None => return Err(format!("invalid offset: {:?}", offset)),
+                }
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(result)
+    }
+
+    /// Applies the operator to measurements (or numbers and measurements),
+    /// propagating their independent errors to first order, i.e. adding the
+    /// errors times the partial derivatives in quadrature. Comparisons compare
+    /// the means.
+    fn measurement_binary(self, (x, a): (f64, f64), (y, b): (f64, f64)) -> Result<Value, String> {
+        let (mean, sigma) = match self {
+            MathOperator::Add => (x + y, a.hypot(b)),
+            MathOperator::Sub => (x - y, a.hypot(b)),
+            MathOperator::Mul => (x * y, (y * a).hypot(x * b)),
+            MathOperator::Div => (x / y, (a / y).hypot(x * b / (y * y))),
+            MathOperator::Pow => {
+                let f = x.powf(y);
+                // the term for the exponent's error is left out when it's
+                // exact, since ln(x) may not exist
+                let db = if b == 0.0 { 0.0 } else { f * x.ln() * b };
+                (f, (y * x.powf(y - 1.0) * a).hypot(db))
+            }
+            MathOperator::Eq
+            | MathOperator::Ne
+            | MathOperator::Gt
+            | MathOperator::Ge
+            | MathOperator::Lt
+            | MathOperator::Le => return self.binary(Value::Number(x), Value::Number(y)),
+            _ => return Err(format!("invalid type: {}", self)),
+        };
+        Ok(Value::Measurement(mean, sigma))
+    }
+}
+
+/// `n!`, generalized to non-integers as `gamma(n + 1)`
+fn factorial(n: f64) -> f64 {
+    if n.fract() != 0.0 || n > 170.0 {
+        return gamma(n + 1.0);
+    }
+    if n < 0.0 {
+        return f64::NAN;
+    }
+    (2..=n as u32).map(f64::from).product()
+}
+
+/// The gamma function, with the Lanczos approximation (g = 7, n = 9)
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // the reflection formula
+        return std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    // t^(x + 0.5) in two halves, which would overflow on its own before the
+    // whole product does
+    let half = t.powf((x + 0.5) / 2.0);
+    (2.0 * std::f64::consts::PI).sqrt() * half * (half * (-t).exp()) * sum
+}
+
+/// Length of a duration or quantity of time in seconds
+fn seconds(x: &Value) -> Option<f64> {
+    match x {
+        Value::Duration(seconds) => Some(*seconds),
+        Value::Quantity(num, unit) => unit.convert(*num, &Unit::base(Dimension::TIME)),
+        _ => None,
+    }
+}
+
+/// `x` to the power of `n`, wrapping around in 128 bits, by squaring
+fn wrapping_pow(mut x: i128, mut n: u128) -> i128 {
+    let mut result: i128 = 1;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = result.wrapping_mul(x);
+        }
+        x = x.wrapping_mul(x);
+        n >>= 1;
+    }
+    result
+}
+
+/// Largest integer that every smaller one can be exactly represented as a
+/// float, 2^53
+pub const MAX_EXACT: f64 = 9007199254740992.0;
+
+/// A number as a 64-bit integer, for the bitwise operators without a word
+/// size. Numbers bigger than 2^53 are out of range, since a float can't hold
+/// their bits exactly.
+fn integer(x: &Value, op: MathOperator) -> Result<i64, String> {
+    match *x {
+        Value::Number(num) if num.fract() == 0.0 && num.abs() <= MAX_EXACT => Ok(num as i64),
+        Value::Number(num) if num.fract() == 0.0 => Err(format!("out of range: {}", op)),
+        _ => Err(format!("invalid type: {}", op)),
+    }
+}
+
+/// A shift amount, which can't be negative
+fn shift(amount: i64, op: MathOperator) -> Result<u32, String> {
+    u32::try_from(amount).map_err(|_| format!("invalid shift: {}", op))
+}
+
+/// Returns the numbers in a list, or `None` if it has anything else
+fn numbers(list: &[Value]) -> Option<Vec<f64>> {
+    list.iter()
+        .map(|x| match *x {
+            Value::Number(num) => Some(num),
+            Value::Integer(n) => Some(n as f64),
+            _ => None,
+        })
+        .collect()
+}
+
+fn number_list(nums: Vec<f64>) -> Value {
+    Value::List(nums.into_iter().map(Value::Number).collect())
+}
+
+/// Returns both numbers or quantities in the same unit so they can be
+/// compared, or `None` if they can't be
+fn compare_quantities(x: &Value, y: &Value) -> Option<(f64, f64)> {
+    match (x, y) {
+        (Value::Number(x), Value::Number(y)) => Some((*x, *y)),
+        (Value::Quantity(x, u), Value::Quantity(y, v)) => Some((*x, v.convert(*y, u)?)),
+        (Value::DateTime(x), Value::DateTime(y)) => Some((x.seconds, y.seconds)),
+        (Value::Duration(_), _) | (_, Value::Duration(_)) => Some((seconds(x)?, seconds(y)?)),
+        _ => None,
+    }
+}
+
+/// Orders numbers, quantities of the same dimension and strings
+fn compare(x: &Value, y: &Value) -> Option<Ordering> {
+    match (x, y) {
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        (Value::Integer(x), Value::Integer(y)) => Some(x.cmp(y)),
+        _ => {
+            let (x, y) = compare_quantities(x, y)?;
+            Some(x.partial_cmp(&y).unwrap_or(Ordering::Equal))
+        }
     }
 }
 
@@ -207,6 +1413,9 @@ mod test {
         assert_eq!(MathOperator::from_str("&"), Ok(MathOperator::And));
         assert_eq!(MathOperator::from_str("|"), Ok(MathOperator::Or));
         assert_eq!(MathOperator::from_str("!"), Ok(MathOperator::Not));
+        assert_eq!(MathOperator::from_str("++"), Ok(MathOperator::Concat));
+        assert_eq!(MathOperator::from_str("concat"), Ok(MathOperator::ConcatFn));
+        assert_eq!(MathOperator::from_str("substr"), Ok(MathOperator::Substr));
         assert_eq!(MathOperator::from_str("foo"), Err(()));
     }
 
@@ -300,4 +1509,70 @@ mod test {
         MathOperator::Not.rpn_exec(&mut stack).unwrap();
         assert_eq!(stack, vec![Value::Boolean(true)]);
     }
+
+    #[test]
+    fn test_math_operator_strings() {
+        let s = |s: &str| Value::String(s.to_string());
+
+        let mut stack = vec![s("foo"), s("bar")];
+        MathOperator::Concat.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![s("foobar")]);
+
+        let mut stack = vec![s("héllo")];
+        MathOperator::Len.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![Value::Number(5.0)]);
+
+        let mut stack = vec![s("Hello")];
+        MathOperator::Upper.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![s("HELLO")]);
+
+        let mut stack = vec![s("Hello")];
+        MathOperator::Lower.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![s("hello")]);
+
+        let mut stack = vec![s("hello"), Value::Number(1.0), Value::Number(3.0)];
+        MathOperator::Substr.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![s("ell")]);
+
+        let mut stack = vec![s("hello"), Value::Number(3.0), Value::Number(10.0)];
+        MathOperator::Substr.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![s("lo")]);
+
+        let mut stack = vec![Value::Number(2.5)];
+        MathOperator::Str.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![s("2.5")]);
+
+        let mut stack = vec![s(" 2.5 ")];
+        MathOperator::Num.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![Value::Number(2.5)]);
+
+        let mut stack = vec![s("abc")];
+        assert!(MathOperator::Num.rpn_exec(&mut stack).is_err());
+
+        let mut stack = vec![s("abc"), s("abd")];
+        MathOperator::Lt.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![Value::Boolean(true)]);
+
+        let mut stack = vec![s("abc"), s("abc")];
+        MathOperator::Eq.rpn_exec(&mut stack).unwrap();
+        assert_eq!(stack, vec![Value::Boolean(true)]);
+
+        let mut stack = vec![s("abc"), Value::Number(1.0)];
+        assert_eq!(
+            MathOperator::Concat.rpn_exec(&mut stack),
+            Err("invalid type: ++".to_string())
+        );
+    }
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(factorial(0.0), 1.0);
+        assert_eq!(factorial(5.0), 120.0);
+        assert_eq!(factorial(20.0), 2_432_902_008_176_640_000.0);
+        assert!((factorial(0.5) - std::f64::consts::PI.sqrt() / 2.0).abs() < 1e-14);
+        assert!((factorial(-0.5) - std::f64::consts::PI.sqrt()).abs() < 1e-14);
+        assert!((factorial(170.5) / 9.483367566824795e307 - 1.0).abs() < 1e-12);
+        assert!(factorial(-2.0).is_nan());
+        assert_eq!(factorial(171.0), f64::INFINITY);
+    }
 }
@@ -0,0 +1,300 @@
+use std::fmt::Display;
+
+/// Why a matrix operation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixError {
+    /// The shapes of the matrices don't fit the operation
+    Shape,
+    Singular,
+}
+
+/// Largest number of rows of a matrix created from a size, like `identity`
+pub const MAX_SIZE: usize = 1000;
+
+/// A dense matrix of numbers, stored row by row
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<f64>,
+}
+
+impl Matrix {
+    /// Creates a matrix from its rows, which must all have the same length
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Option<Matrix> {
+        let cols = rows.first()?.len();
+        if cols == 0 || rows.iter().any(|row| row.len() != cols) {
+            return None;
+        }
+        Some(Matrix {
+            rows: rows.len(),
+            cols,
+            data: rows.concat(),
+        })
+    }
+
+    /// Creates a matrix with one column
+    pub fn column(data: Vec<f64>) -> Matrix {
+        Matrix {
+            rows: data.len(),
+            cols: 1,
+            data,
+        }
+    }
+
+    /// Creates the `n` by `n` identity matrix, which must be at most
+    /// `MAX_SIZE` rows
+    pub fn identity(n: usize) -> Matrix {
+        let mut data = vec![0.0; n * n];
+        for i in 0..n {
+            data[i * n + i] = 1.0;
+        }
+        Matrix {
+            rows: n,
+            cols: n,
+            data,
+        }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.cols + col]
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Matrix {
+        Matrix {
+            data: self.data.iter().map(|x| f(*x)).collect(),
+            ..*self
+        }
+    }
+
+    /// Combines two matrices of the same shape element-wise
+    pub fn zip_with(&self, other: &Matrix, f: impl Fn(f64, f64) -> f64) -> Option<Matrix> {
+        if self.rows != other.rows || self.cols != other.cols {
+            return None;
+        }
+        Some(Matrix {
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(x, y)| f(*x, *y))
+                .collect(),
+            ..*self
+        })
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut data = Vec::with_capacity(self.data.len());
+        for col in 0..self.cols {
+            for row in 0..self.rows {
+                data.push(self.get(row, col));
+            }
+        }
+        Matrix {
+            rows: self.cols,
+            cols: self.rows,
+            data,
+        }
+    }
+
+    /// Matrix multiplication, if the shapes are compatible
+    pub fn matmul(&self, other: &Matrix) -> Option<Matrix> {
+        if self.cols != other.rows {
+            return None;
+        }
+        let mut data = vec![0.0; self.rows * other.cols];
+        for row in 0..self.rows {
+            for col in 0..other.cols {
+                data[row * other.cols + col] = (0..self.cols)
+                    .map(|k| self.get(row, k) * other.get(k, col))
+                    .sum();
+            }
+        }
+        Some(Matrix {
+            rows: self.rows,
+            cols: other.cols,
+            data,
+        })
+    }
+
+    /// Raises a square matrix to an integer power, inverting it for negative
+    /// powers
+    pub fn powi(&self, n: i32) -> Option<Matrix> {
+        if !self.is_square() {
+            return None;
+        }
+        let mut base = if n < 0 { self.inverse()? } else { self.clone() };
+        let mut result = Matrix::identity(self.rows);
+        // exponentiation by squaring
+        let mut n = n.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.matmul(&base)?;
+            }
+            n >>= 1;
+            if n > 0 {
+                base = base.matmul(&base)?;
+            }
+        }
+        Some(result)
+    }
+
+    /// Determinant of a square matrix, by Gaussian elimination
+    pub fn det(&self) -> Option<f64> {
+        if !self.is_square() {
+            return None;
+        }
+        let n = self.rows;
+        let mut a = self.data.clone();
+        let mut det = 1.0;
+        for col in 0..n {
+            let pivot = (col..n)
+                .max_by(|&i, &j| a[i * n + col].abs().total_cmp(&a[j * n + col].abs()))
+                .unwrap();
+            if a[pivot * n + col] == 0.0 {
+                return Some(0.0);
+            }
+            if pivot != col {
+                for k in 0..n {
+                    a.swap(pivot * n + k, col * n + k);
+                }
+                det = -det;
+            }
+            det *= a[col * n + col];
+            for row in col + 1..n {
+                let factor = a[row * n + col] / a[col * n + col];
+                for k in col..n {
+                    a[row * n + k] -= factor * a[col * n + k];
+                }
+            }
+        }
+        Some(det)
+    }
+
+    /// Solves `self * x = b` for `x` by Gaussian elimination with partial
+    /// pivoting
+    pub fn solve(&self, b: &Matrix) -> Result<Matrix, MatrixError> {
+        if !self.is_square() || b.rows != self.rows {
+            return Err(MatrixError::Shape);
+        }
+        let n = self.rows;
+        let m = b.cols;
+        let mut a = self.data.clone();
+        let mut x = b.data.clone();
+        let scale = a.iter().fold(0.0f64, |max, v| max.max(v.abs()));
+        for col in 0..n {
+            let pivot = (col..n)
+                .max_by(|&i, &j| a[i * n + col].abs().total_cmp(&a[j * n + col].abs()))
+                .unwrap();
+            if a[pivot * n + col].abs() <= scale * n as f64 * f64::EPSILON {
+                return Err(MatrixError::Singular);
+            }
+            for k in 0..n {
+                a.swap(pivot * n + k, col * n + k);
+            }
+            for k in 0..m {
+                x.swap(pivot * m + k, col * m + k);
+            }
+            for row in col + 1..n {
+                let factor = a[row * n + col] / a[col * n + col];
+                for k in col..n {
+                    a[row * n + k] -= factor * a[col * n + k];
+                }
+                for k in 0..m {
+                    x[row * m + k] -= factor * x[col * m + k];
+                }
+            }
+        }
+        // back substitution
+        for row in (0..n).rev() {
+            for k in 0..m {
+                let sum: f64 = (row + 1..n).map(|j| a[row * n + j] * x[j * m + k]).sum();
+                x[row * m + k] = (x[row * m + k] - sum) / a[row * n + row];
+            }
+        }
+        Ok(Matrix {
+            rows: n,
+            cols: m,
+            data: x,
+        })
+    }
+
+    pub fn inverse(&self) -> Option<Matrix> {
+        self.solve(&Matrix::identity(self.rows)).ok()
+    }
+}
+
+impl Display for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, &|num| num.to_string(), false)
+    }
+}
+
+impl Matrix {
+    /// Writes the matrix like `Display`, with `number` writing its entries. If
+    /// `commas`, the entries can have commas in them, so they're separated by
+    /// spaces instead, like in RPN
+    pub fn write(
+        &self,
+        f: &mut impl std::fmt::Write,
+        number: &dyn Fn(f64) -> String,
+        commas: bool,
+    ) -> std::fmt::Result {
+        write!(f, "[")?;
+        for row in 0..self.rows {
+            if row > 0 {
+                write!(f, "; ")?;
+            }
+            for col in 0..self.cols {
+                if col > 0 {
+                    write!(f, "{}", if commas { " " } else { ", " })?;
+                }
+                write!(f, "{}", number(self.get(row, col)))?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_matrix() {
+        let a = Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap();
+        assert_eq!(a.to_string(), "[1, 2; 3, 4]");
+        assert_eq!(a.transpose().to_string(), "[1, 3; 2, 4]");
+        assert_eq!(a.det(), Some(-2.0));
+        assert_eq!(a.matmul(&Matrix::identity(2)), Some(a.clone()));
+        assert_eq!(a.matmul(&a).unwrap().to_string(), "[7, 10; 15, 22]");
+        assert_eq!(a.powi(2), a.matmul(&a));
+        assert_eq!(a.powi(0), Some(Matrix::identity(2)));
+        assert_eq!(a.powi(5).unwrap().to_string(), "[1069, 1558; 2337, 3406]");
+        let inv = a.inverse().unwrap();
+        let expected = [-2.0, 1.0, 1.5, -0.5];
+        assert!(inv
+            .data
+            .iter()
+            .zip(expected)
+            .all(|(x, y)| (x - y).abs() < 1e-12));
+        assert_eq!(
+            a.solve(&Matrix::column(vec![5.0, 11.0])),
+            Ok(Matrix::column(vec![1.0, 2.0]))
+        );
+
+        let singular = Matrix::from_rows(vec![vec![1.0, 2.0], vec![2.0, 4.0]]).unwrap();
+        assert_eq!(singular.det(), Some(0.0));
+        assert_eq!(singular.inverse(), None);
+
+        let wide = Matrix::from_rows(vec![vec![1.0, 2.0, 3.0]]).unwrap();
+        assert_eq!(wide.det(), None);
+        assert_eq!(wide.matmul(&a), None);
+        assert_eq!(a.matmul(&wide.transpose()), None);
+        assert!(Matrix::from_rows(vec![vec![1.0], vec![1.0, 2.0]]).is_none());
+    }
+}
@@ -1,19 +1,554 @@
-use std::str::FromStr;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+};
 
-use crate::math::{MathOperator, Value};
+use crate::{
+    datetime::{self, DateTime},
+    format::WordSize,
+    interval::{self, Bounds},
+    math::{self, MathOperator, Value},
+    matrix::Matrix,
+    units::Unit,
+    utils::{
+        offset_in, parse_big_integer, parse_number, parse_string_literal, split_tokens, LineError,
+    },
+};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum RpnItem {
     Operand(f64),
+    /// An integer literal too big to be exact as a float, which is kept exact
+    /// with a word size
+    Integer(i128),
     Boolean(bool),
+    String(String),
+    DateTime(DateTime),
+    /// A duration in seconds
+    Duration(f64),
     Operator(MathOperator),
+    Builtin(Builtin),
+    /// Attaches a unit to the value on top of the stack
+    Unit(Unit),
+    /// Converts the value on top of the stack to a unit (`in km/h`)
+    Convert(Unit),
+    /// Starts a list literal; everything pushed until the matching
+    /// [`RpnItem::ListEnd`] becomes an element
+    ListStart,
+    ListEnd,
+    /// Ends a row of a matrix literal like `[1 2 ; 3 4]`
+    RowEnd,
+    /// Quoted code, pushed as a [`Value::Block`]
+    Block(Vec<RpnItem>),
+    /// Pops a value into a local variable slot
+    Store(usize),
+    /// Pushes the value of a local variable slot
+    Load(usize),
+    /// Runs a word defined in the interpreter, e.g. a function defined with
+    /// `f(x) = x^2` in infix
+    Call(String),
+}
+
+/// Operators that rearrange the stack or run quoted code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    Dup,
+    Drop,
+    Swap,
+    Over,
+    /// Moves the third value from the top to the top, e.g. `1 2 3 rot` is
+    /// `2 3 1`
+    Rot,
+    /// Runs a block on the stack, e.g. `3 { dup * } exec`
+    Exec,
+    /// Defines a word that runs a block, e.g. `{ dup * } 'sq def`
+    Def,
+    /// Runs a block some number of times, e.g. `1 10 { 2 * } times`
+    Times,
+    /// Runs the second block while the first one returns `true`, e.g.
+    /// `1 { dup 100 < } { 2 * } while`
+    While,
+    /// Runs a block with each number from a start to an end by a step, e.g.
+    /// `0 1 10 1 { + } for`
+    For,
+    /// Runs a block with each element of a list, e.g. `0 [1 2 3] { + } each`
+    Each,
+    /// Runs the first block, and if it fails, the second one with the stack as
+    /// it was and the error on top, e.g. `{ 1 0 / } { errmsg } try`
+    Try,
+    /// Fails with a message, whose kind is before a `: ` as in
+    /// `"bad input: negative" throw`, or rethrows a caught error
+    Throw,
+    Map,
+    Filter,
+    Fold,
+    /// Runs one of two blocks depending on a boolean, e.g.
+    /// `x 0 != { 1 x / } { 0 } ifte`
+    Ifte,
+    /// `&` and `|` that only run the block on the right if the boolean on the
+    /// left doesn't decide the result, e.g. `x 0 != { 1 x / 2 > } andthen`
+    AndThen,
+    OrElse,
+}
+
+impl FromStr for Builtin {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dup" => Ok(Builtin::Dup),
+            "drop" => Ok(Builtin::Drop),
+            "swap" => Ok(Builtin::Swap),
+            "over" => Ok(Builtin::Over),
+            "rot" => Ok(Builtin::Rot),
+            "exec" => Ok(Builtin::Exec),
+            "def" => Ok(Builtin::Def),
+            "times" => Ok(Builtin::Times),
+            "while" => Ok(Builtin::While),
+            "for" => Ok(Builtin::For),
+            "each" => Ok(Builtin::Each),
+            "try" => Ok(Builtin::Try),
+            "throw" => Ok(Builtin::Throw),
+            "map" => Ok(Builtin::Map),
+            "filter" => Ok(Builtin::Filter),
+            "fold" => Ok(Builtin::Fold),
+            "ifte" => Ok(Builtin::Ifte),
+            "andthen" => Ok(Builtin::AndThen),
+            "orelse" => Ok(Builtin::OrElse),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Builtin::Dup => "dup",
+            Builtin::Drop => "drop",
+            Builtin::Swap => "swap",
+            Builtin::Over => "over",
+            Builtin::Rot => "rot",
+            Builtin::Exec => "exec",
+            Builtin::Def => "def",
+            Builtin::Times => "times",
+            Builtin::While => "while",
+            Builtin::For => "for",
+            Builtin::Each => "each",
+            Builtin::Try => "try",
+            Builtin::Throw => "throw",
+            Builtin::Map => "map",
+            Builtin::Filter => "filter",
+            Builtin::Fold => "fold",
+            Builtin::Ifte => "ifte",
+            Builtin::AndThen => "andthen",
+            Builtin::OrElse => "orelse",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Builtin {
+    /// Number of operands the builtin takes from the stack
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Dup | Builtin::Drop | Builtin::Exec | Builtin::Throw => 1,
+            Builtin::Swap | Builtin::Over | Builtin::Def => 2,
+            Builtin::Times | Builtin::While | Builtin::Each | Builtin::Try => 2,
+            Builtin::Map | Builtin::Filter | Builtin::AndThen | Builtin::OrElse => 2,
+            Builtin::Rot | Builtin::Fold | Builtin::Ifte => 3,
+            Builtin::For => 4,
+        }
+    }
+
+    /// Whether the builtin only makes sense in RPN, since it doesn't leave one
+    /// result on the stack
+    pub fn is_rpn_only(self) -> bool {
+        matches!(
+            self,
+            Builtin::Dup
+                | Builtin::Drop
+                | Builtin::Swap
+                | Builtin::Over
+                | Builtin::Rot
+                | Builtin::Exec
+                | Builtin::Def
+                | Builtin::Times
+                | Builtin::While
+                | Builtin::For
+                | Builtin::Each
+                | Builtin::Try
+                | Builtin::Throw
+        )
+    }
+
+    fn exec(
+        self,
+        interpreter: &Interpreter,
+        stack: &mut Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+    ) -> Result<(), String> {
+        match self {
+            Builtin::Dup | Builtin::Drop | Builtin::Swap | Builtin::Over | Builtin::Rot => {
+                return self.shuffle(stack)
+            }
+            Builtin::Exec => {
+                return match stack.pop() {
+                    Some(Value::Block(block)) => {
+                        interpreter.run_nested("exec", &block, stack, locals)
+                    }
+                    Some(_) => Err(format!("invalid type: {}", self)),
+                    None => Err("invalid syntax: too few operands".into()),
+                }
+            }
+            Builtin::Def => return define_word(interpreter, stack),
+            Builtin::Try => return try_catch(interpreter, stack, locals),
+            Builtin::Throw => {
+                return match stack.pop() {
+                    Some(Value::String(message)) => Err(message),
+                    Some(Value::Error(kind, message)) => Err(format!("{}: {}", kind, message)),
+                    Some(_) => Err(format!("invalid type: {}", self)),
+                    None => Err("invalid syntax: too few operands".into()),
+                }
+            }
+            Builtin::Times | Builtin::While | Builtin::For | Builtin::Each => {
+                return self.repeat(interpreter, stack, locals)
+            }
+            Builtin::Ifte => return if_then_else(interpreter, stack, locals),
+            Builtin::AndThen | Builtin::OrElse => {
+                return self.short_circuit(interpreter, stack, locals)
+            }
+            _ => {}
+        }
+        let block = match stack.pop() {
+            Some(Value::Block(block)) => block,
+            Some(_) => return Err(format!("invalid type: {}", self)),
+            None => return Err("invalid syntax: too few operands".into()),
+        };
+        let result = match self {
+            Builtin::Map => {
+                let list = pop_list(stack, self)?;
+                let list = list
+                    .into_iter()
+                    .map(|x| interpreter.call(&block, vec![x], locals))
+                    .collect::<Result<_, _>>()?;
+                Value::List(list)
+            }
+            Builtin::Filter => {
+                let mut result = Vec::new();
+                for x in pop_list(stack, self)? {
+                    match interpreter.call(&block, vec![x.clone()], locals)? {
+                        Value::Boolean(true) => result.push(x),
+                        Value::Boolean(false) => {}
+                        _ => return Err(format!("invalid type: {}", self)),
+                    }
+                }
+                Value::List(result)
+            }
+            Builtin::Fold => {
+                let init = stack.pop().ok_or("invalid syntax: too few operands")?;
+                pop_list(stack, self)?
+                    .into_iter()
+                    .try_fold(init, |acc, x| {
+                        interpreter.call(&block, vec![acc, x], locals)
+                    })?
+            }
+            _ => unreachable!(),
+        };
+        stack.push(result);
+        Ok(())
+    }
+}
+
+impl Builtin {
+    /// Runs the stack words `dup`, `drop`, `swap`, `over` and `rot`
+    fn shuffle(self, stack: &mut Vec<Value>) -> Result<(), String> {
+        if stack.len() < self.arity() {
+            return Err("invalid syntax: too few operands".into());
+        }
+        let top = stack.len() - 1;
+        match self {
+            Builtin::Dup => stack.push(stack[top].clone()),
+            Builtin::Drop => {
+                stack.pop();
+            }
+            Builtin::Swap => stack.swap(top - 1, top),
+            Builtin::Over => stack.push(stack[top - 1].clone()),
+            Builtin::Rot => {
+                let x = stack.remove(top - 2);
+                stack.push(x);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Runs the loops `times`, `while`, `for` and `each`, whose block is on top
+    /// of the stack
+    fn repeat(
+        self,
+        interpreter: &Interpreter,
+        stack: &mut Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+    ) -> Result<(), String> {
+        if stack.len() < self.arity() {
+            return Err("invalid syntax: too few operands".into());
+        }
+        let block = match stack.pop().unwrap() {
+            Value::Block(block) => block,
+            _ => return Err(format!("invalid type: {}", self)),
+        };
+        let mut pop_number = || match stack.pop().unwrap() {
+            Value::Number(x) => Ok(x),
+            Value::Integer(n) => Ok(n as f64),
+            _ => Err(format!("invalid type: {}", self)),
+        };
+        match self {
+            Builtin::Times => {
+                let count = pop_number()?;
+                if count < 0.0 || count.fract() != 0.0 {
+                    return Err(format!("invalid count: {}", self));
+                }
+                for _ in 0..count as u64 {
+                    interpreter.run(&block, stack, locals)?;
+                }
+            }
+            Builtin::While => {
+                let condition = match stack.pop().unwrap() {
+                    Value::Block(condition) => condition,
+                    _ => return Err(format!("invalid type: {}", self)),
+                };
+                loop {
+                    interpreter.run(&condition, stack, locals)?;
+                    match stack.pop() {
+                        Some(Value::Boolean(true)) => interpreter.run(&block, stack, locals)?,
+                        Some(Value::Boolean(false)) => break,
+                        Some(_) => return Err(format!("invalid type: {}", self)),
+                        None => return Err("invalid syntax: too few operands".into()),
+                    }
+                }
+            }
+            Builtin::For => {
+                let step = pop_number()?;
+                let end = pop_number()?;
+                let start = pop_number()?;
+                if step == 0.0 || step.is_nan() {
+                    return Err(format!("invalid step: {}", self));
+                }
+                // counting the steps up front, with some room for rounding
+                // errors, so `0 0.3 0.1` ends at 0.3 even though 0.3 / 0.1 is
+                // a bit less than 3
+                let count = ((end - start) / step + 1e-9).floor();
+                let mut i = 0.0;
+                while i <= count {
+                    stack.push(Value::Number(start + i * step));
+                    interpreter.run(&block, stack, locals)?;
+                    i += 1.0;
+                }
+            }
+            Builtin::Each => {
+                for x in pop_list(stack, self)? {
+                    stack.push(x);
+                    interpreter.run(&block, stack, locals)?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn short_circuit(
+        self,
+        interpreter: &Interpreter,
+        stack: &mut Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+    ) -> Result<(), String> {
+        if stack.len() < 2 {
+            return Err("invalid syntax: too few operands".into());
+        }
+        // `false` decides `andthen`, and `true` decides `orelse`
+        let decisive = self == Builtin::OrElse;
+        let right = stack.pop().unwrap();
+        let result = match (stack.pop().unwrap(), right) {
+            (Value::Boolean(x), _) if x == decisive => x,
+            (Value::Boolean(_), Value::Boolean(y)) => y,
+            (Value::Boolean(_), Value::Block(block)) => {
+                match interpreter.call(&block, Vec::new(), locals)? {
+                    Value::Boolean(y) => y,
+                    _ => return Err(format!("invalid type: {}", self)),
+                }
+            }
+            _ => return Err(format!("invalid type: {}", self)),
+        };
+        stack.push(Value::Boolean(result));
+        Ok(())
+    }
+}
+
+/// Runs the branch chosen by the condition under it on the stack, which is
+/// only pushed if it isn't a block
+fn if_then_else(
+    interpreter: &Interpreter,
+    stack: &mut Vec<Value>,
+    locals: &mut Vec<Option<Value>>,
+) -> Result<(), String> {
+    if stack.len() < 3 {
+        return Err("invalid syntax: too few operands".into());
+    }
+    let otherwise = stack.pop().unwrap();
+    let then = stack.pop().unwrap();
+    let branch = match stack.pop().unwrap() {
+        Value::Boolean(true) => then,
+        Value::Boolean(false) => otherwise,
+        _ => return Err("invalid type: ifte".into()),
+    };
+    match branch {
+        Value::Block(block) => interpreter.run(&block, stack, locals),
+        x => {
+            stack.push(x);
+            Ok(())
+        }
+    }
+}
+
+/// Defines a word named by the string on top of the stack, running the block
+/// under it
+fn define_word(interpreter: &Interpreter, stack: &mut Vec<Value>) -> Result<(), String> {
+    if stack.len() < 2 {
+        return Err("invalid syntax: too few operands".into());
+    }
+    let name = stack.pop().unwrap();
+    let (body, name) = match (stack.pop().unwrap(), name) {
+        (Value::Block(body), Value::String(name)) => (body, name),
+        _ => return Err("invalid type: def".into()),
+    };
+    // the name has to parse as a call, or the word could never be run
+    if name.parse::<RpnItem>() != Ok(RpnItem::Call(name.clone())) {
+        return Err(format!("invalid name: {}", name));
+    }
+    let source = body.iter().fold(format!(": {}", name), |source, item| {
+        format!("{} {}", source, item)
+    });
+    let word = Word {
+        body,
+        arity: None,
+        source: format!("{} ;", source),
+    };
+    interpreter.define(&name, word);
+    Ok(())
+}
+
+/// The index of the `[` of the list in `tokens` that isn't ended
+fn unmatched_list(tokens: &[RpnItem]) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().rev() {
+        match token {
+            RpnItem::ListEnd => depth += 1,
+            RpnItem::ListStart if depth == 0 => return i,
+            RpnItem::ListStart => depth -= 1,
+            _ => {}
+        }
+    }
+    0
+}
+
+/// An error about the item at an index, at the offset of its token
+fn at_offset((i, message): (usize, String), offsets: &[usize]) -> LineError {
+    LineError {
+        message,
+        offset: offsets.get(i).copied().unwrap_or(0),
+    }
+}
+
+/// Ends the list started at the stack height `start`, which is a matrix if
+/// `rows` has the heights where its rows end
+fn end_list(start: usize, mut rows: Vec<usize>, stack: &mut Vec<Value>) -> Result<(), String> {
+    if start > stack.len() {
+        return Err("invalid syntax: too few operands".into());
+    }
+    if rows.is_empty() {
+        let list = stack.split_off(start);
+        stack.push(Value::List(list));
+        return Ok(());
+    }
+
+    if rows.last() != Some(&stack.len()) {
+        rows.push(stack.len());
+    }
+    let mut matrix = Vec::new();
+    let mut row_start = start;
+    for row_end in rows {
+        let row = stack
+            .get(row_start..row_end)
+            .ok_or("invalid syntax: too few operands")?
+            .iter()
+            .map(|x| match *x {
+                Value::Number(num) => Ok(num),
+                Value::Integer(n) => Ok(n as f64),
+                _ => Err("invalid type: matrix"),
+            })
+            .collect::<Result<_, _>>()?;
+        matrix.push(row);
+        row_start = row_end;
+    }
+    stack.truncate(start);
+    let matrix = Matrix::from_rows(matrix).ok_or("invalid shape: matrix")?;
+    stack.push(Value::Matrix(matrix));
+    Ok(())
+}
+
+/// Runs the block under the top of the stack, and if it fails, puts the stack
+/// back as it was and runs the block on top with the error
+fn try_catch(
+    interpreter: &Interpreter,
+    stack: &mut Vec<Value>,
+    locals: &mut Vec<Option<Value>>,
+) -> Result<(), String> {
+    if stack.len() < 2 {
+        return Err("invalid syntax: too few operands".into());
+    }
+    let (body, handler) = match (stack.pop().unwrap(), stack.pop().unwrap()) {
+        (Value::Block(handler), Value::Block(body)) => (body, handler),
+        _ => return Err("invalid type: try".into()),
+    };
+    let before = stack.clone();
+    if let Err(e) = interpreter.run(&body, stack, locals) {
+        *stack = before;
+        stack.push(Value::error(&e));
+        interpreter.run(&handler, stack, locals)?;
+    }
+    Ok(())
+}
+
+fn pop_list(stack: &mut Vec<Value>, builtin: Builtin) -> Result<Vec<Value>, String> {
+    match stack.pop() {
+        Some(Value::List(list)) => Ok(list),
+        Some(_) => Err(format!("invalid type: {}", builtin)),
+        None => Err("invalid syntax: too few operands".into()),
+    }
 }
 
 impl FromStr for RpnItem {
     type Err = String;
 
     fn from_str(s: &str) -> Result<RpnItem, Self::Err> {
-        if let Ok(num) = s.parse::<f64>() {
+        match s {
+            "[" => return Ok(RpnItem::ListStart),
+            "]" => return Ok(RpnItem::ListEnd),
+            ";" => return Ok(RpnItem::RowEnd),
+            _ => {}
+        }
+
+        if let Some(string) = parse_string_literal(s) {
+            return string.map(RpnItem::String);
+        }
+
+        if let Some(n) = parse_big_integer(s) {
+            return Ok(RpnItem::Integer(n));
+        }
+
+        if let Some(num) = parse_number(s).or_else(|| math::constant(s)) {
             return Ok(RpnItem::Operand(num));
         }
 
@@ -21,35 +556,483 @@ impl FromStr for RpnItem {
             return Ok(RpnItem::Boolean(b));
         }
 
+        if let Ok(datetime) = s.parse::<DateTime>() {
+            return Ok(RpnItem::DateTime(datetime));
+        }
+
+        if let Ok(seconds) = datetime::parse_duration(s) {
+            return Ok(RpnItem::Duration(seconds));
+        }
+
         if let Ok(op) = s.parse::<MathOperator>() {
             return Ok(RpnItem::Operator(op));
         }
 
+        if let Ok(builtin) = s.parse::<Builtin>() {
+            return Ok(RpnItem::Builtin(builtin));
+        }
+
+        if let Ok(unit) = s.parse::<Unit>() {
+            return Ok(RpnItem::Unit(unit));
+        }
+
+        // a quoted name, as in `'sq def`
+        if let Some(name) = s.strip_prefix('\'').filter(|name| is_name(name)) {
+            return Ok(RpnItem::String(name.into()));
+        }
+
         match s.chars().nth(0) {
-            Some(c) if c.is_digit(10) => Err(format!("Invalid Number: {}", s).into()),
-            _ => Err(format!("Invalid Operator: {}", s).into()),
+            Some(c) if c.is_ascii_digit() => Err(format!("Invalid Number: {}", s)),
+            _ if is_name(s) => Ok(RpnItem::Call(s.into())),
+            _ => Err(format!("Invalid Operator: {}", s)),
+        }
+    }
+}
+
+/// Whether `s` can name a word, like `sq` or `area_2`
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+impl Display for RpnItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            RpnItem::Operand(num) => write!(f, "{}", num),
+            RpnItem::Integer(n) => write!(f, "{}", n),
+            RpnItem::Boolean(b) => write!(f, "{}", b),
+            RpnItem::String(ref s) => write!(f, "{:?}", s),
+            RpnItem::DateTime(datetime) => write!(f, "{}", datetime),
+            RpnItem::Duration(seconds) => write!(f, "{}", datetime::format_duration(seconds)),
+            RpnItem::Operator(op) => write!(f, "{}", op),
+            RpnItem::Builtin(builtin) => write!(f, "{}", builtin),
+            RpnItem::Unit(ref unit) => write!(f, "{}", unit),
+            RpnItem::Convert(ref unit) => write!(f, "in {}", unit),
+            RpnItem::ListStart => write!(f, "["),
+            RpnItem::ListEnd => write!(f, "]"),
+            RpnItem::RowEnd => write!(f, ";"),
+            RpnItem::Block(ref items) => write!(f, "{}", Value::Block(items.clone())),
+            RpnItem::Store(slot) => write!(f, "->${}", slot),
+            RpnItem::Load(slot) => write!(f, "${}", slot),
+            RpnItem::Call(ref name) => write!(f, "{}", name),
         }
     }
 }
 
 pub fn execute_rpn(tokens: &[RpnItem]) -> Result<Value, String> {
-    let mut stack = Vec::new();
-    for token in tokens {
+    Interpreter::default().execute(tokens)
+}
+
+/// How many words can be running at once, so runaway recursion ends with an
+/// error instead of overflowing the stack
+pub const MAX_DEPTH: usize = 200;
+
+/// How many items can be run by one call to [`Interpreter::execute`], so
+/// runaway loops end with an error instead of hanging
+pub const MAX_STEPS: usize = 10_000_000;
+
+/// Named code defined in the interpreter, which runs on the stack
+#[derive(Clone, Debug, PartialEq)]
+pub struct Word {
+    pub body: Vec<RpnItem>,
+    /// The number of arguments it takes, if it's known, so calls can be checked
+    pub arity: Option<usize>,
+    /// How it was defined, e.g. `f(x) = x^2`, for listing definitions
+    pub source: String,
+}
+
+/// Settings that code is run with, and the words defined so far
+#[derive(Clone, Debug, Default)]
+pub struct Interpreter {
+    /// Truncates and wraps numbers to fit in a word, like a programmer's
+    /// calculator
+    pub word_size: Option<WordSize>,
+    /// Makes operators that turn finite numbers into infinities or NaNs, like
+    /// `1 0 /`, fail instead
+    pub strict_ieee: bool,
+    pub words: RefCell<HashMap<String, Word>>,
+    /// Warnings, like about redefining a word, for the caller to show
+    pub warnings: RefCell<Vec<String>>,
+    /// The number of words running
+    depth: Cell<usize>,
+    /// The number of items run so far
+    steps: Cell<usize>,
+}
+
+impl Interpreter {
+    /// Defines a word, warning if it replaces one
+    pub fn define(&self, name: &str, word: Word) {
+        if self.words.borrow_mut().insert(name.into(), word).is_some() {
+            self.warnings
+                .borrow_mut()
+                .push(format!("redefining {}", name));
+        }
+    }
+
+    fn call_word(&self, name: &str, stack: &mut Vec<Value>) -> Result<(), String> {
+        let body = match self.words.borrow().get(name) {
+            Some(word) => word.body.clone(),
+            None => return Err(format!("unknown word: {}", name)),
+        };
+        // words have their own local variables
+        self.run_nested(name, &body, stack, &mut Vec::new())
+    }
+
+    /// Runs code called by `name`, like a word or `exec`, keeping track of how
+    /// deep the calls go
+    fn run_nested(
+        &self,
+        name: &str,
+        tokens: &[RpnItem],
+        stack: &mut Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+    ) -> Result<(), String> {
+        if self.depth.get() >= MAX_DEPTH {
+            return Err(format!("recursion too deep: {}", name));
+        }
+        self.depth.set(self.depth.get() + 1);
+        let result = self.run(tokens, stack, locals);
+        self.depth.set(self.depth.get() - 1);
+        result
+    }
+
+    pub fn execute(&self, tokens: &[RpnItem]) -> Result<Value, String> {
+        self.run_line(tokens, false)
+            .map(Option::unwrap)
+            .map_err(|(_, e)| e)
+    }
+
+    /// Like [`Interpreter::execute`], except that code leaving nothing on the
+    /// stack, like a definition, has no result
+    pub fn execute_line(&self, tokens: &[RpnItem]) -> Result<Option<Value>, String> {
+        self.run_line(tokens, true).map_err(|(_, e)| e)
+    }
+
+    /// Like [`Interpreter::execute`], reporting errors at the byte offset in
+    /// the line of the token the item they're about comes from, where
+    /// `offsets` are the offsets of the items' tokens
+    pub fn execute_with_offsets(
+        &self,
+        tokens: &[RpnItem],
+        offsets: &[usize],
+    ) -> Result<Value, LineError> {
+        self.run_line(tokens, false)
+            .map(Option::unwrap)
+            .map_err(|e| at_offset(e, offsets))
+    }
+
+    /// Like [`Interpreter::execute_line`], reporting errors like
+    /// [`Interpreter::execute_with_offsets`]
+    pub fn execute_line_with_offsets(
+        &self,
+        tokens: &[RpnItem],
+        offsets: &[usize],
+    ) -> Result<Option<Value>, LineError> {
+        self.run_line(tokens, true)
+            .map_err(|e| at_offset(e, offsets))
+    }
+
+    /// Runs a line on a new stack, returning the value it leaves, which can
+    /// be nothing if `optional`, or the error and the index of the item it's
+    /// about, which is the first one if there are too many operands
+    fn run_line(
+        &self,
+        tokens: &[RpnItem],
+        optional: bool,
+    ) -> Result<Option<Value>, (usize, String)> {
+        let mut stack = Vec::new();
+        self.steps.set(0);
+        self.run_at(tokens, &mut stack, &mut Vec::new())?;
+        match stack.len() {
+            0 if optional => Ok(None),
+            1 => Ok(stack.pop()),
+            _ => Err((0, "invalid syntax: too many operands".to_string())),
+        }
+    }
+
+    /// Wraps the numbers in `x` to the word size, if there's one, and
+    /// otherwise makes integers floats
+    fn wrap(&self, x: Value) -> Result<Value, String> {
+        match (self.word_size, x) {
+            (Some(word), x) => word.wrap_value(x),
+            (None, Value::Integer(n)) => Ok(Value::Number(n as f64)),
+            (None, x) => Ok(x),
+        }
+    }
+
+    /// Counts a step, failing if there have been too many
+    fn step(&self) -> Result<(), String> {
+        if self.steps.get() >= MAX_STEPS {
+            return Err(format!("too many steps: {}", MAX_STEPS));
+        }
+        self.steps.set(self.steps.get() + 1);
+        Ok(())
+    }
+
+    /// Runs `tokens` on `stack`, with `locals` holding the local variables of the
+    /// code being run
+    fn run(
+        &self,
+        tokens: &[RpnItem],
+        stack: &mut Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+    ) -> Result<(), String> {
+        self.run_at(tokens, stack, locals).map_err(|(_, e)| e)
+    }
+
+    /// Like [`Interpreter::run`], also returning the index of the item an
+    /// error is about
+    fn run_at(
+        &self,
+        tokens: &[RpnItem],
+        stack: &mut Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+    ) -> Result<(), (usize, String)> {
+        // the stack heights where the lists being built start, and where the
+        // rows of the ones that are matrices end
+        let mut lists = Vec::new();
+        // running even an empty block is a step, so `1e15 { } times` ends too
+        self.step().map_err(|e| (0, e))?;
+        for (i, token) in tokens.iter().enumerate() {
+            self.run_item(token, stack, locals, &mut lists)
+                .map_err(|e| (i, e))?;
+        }
+        if !lists.is_empty() {
+            return Err((unmatched_list(tokens), "invalid syntax: unmatched [".into()));
+        }
+        Ok(())
+    }
+
+    /// Runs one item of the code [`Interpreter::run`] runs
+    fn run_item(
+        &self,
+        token: &RpnItem,
+        stack: &mut Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+        lists: &mut Vec<(usize, Vec<usize>)>,
+    ) -> Result<(), String> {
+        self.step()?;
         match *token {
-            RpnItem::Operand(num) => stack.push(Value::Number(num)),
+            RpnItem::Operand(num) => stack.push(self.wrap(Value::Number(num))?),
+            RpnItem::Integer(n) => stack.push(self.wrap(Value::Integer(n))?),
             RpnItem::Boolean(b) => stack.push(Value::Boolean(b)),
-            RpnItem::Operator(op) => op.rpn_exec(&mut stack)?,
+            RpnItem::String(ref s) => stack.push(Value::String(s.clone())),
+            RpnItem::DateTime(datetime) => stack.push(Value::DateTime(datetime)),
+            RpnItem::Duration(seconds) => stack.push(Value::Duration(seconds)),
+            RpnItem::Operator(
+                op @ (MathOperator::Popcount | MathOperator::Clz | MathOperator::Ctz),
+            ) if self.word_size.is_some() => {
+                let x = stack.pop().ok_or("invalid syntax: too few operands")?;
+                stack.push(self.word_size.unwrap().count_bits(op, x)?);
+            }
+            RpnItem::Operator(op) => self.operate(op, stack)?,
+            RpnItem::Builtin(builtin) => builtin.exec(self, stack, locals)?,
+            RpnItem::Unit(_) | RpnItem::Convert(_) => self.apply_unit(token, stack)?,
+            RpnItem::ListStart => lists.push((stack.len(), Vec::new())),
+            RpnItem::ListEnd => {
+                let (start, rows) = lists.pop().ok_or("invalid syntax: unmatched ]")?;
+                end_list(start, rows, stack)?;
+            }
+            RpnItem::RowEnd => match lists.last_mut() {
+                Some((_, rows)) => rows.push(stack.len()),
+                None => return Err("invalid syntax: ; outside of a matrix".into()),
+            },
+            RpnItem::Block(ref block) => stack.push(Value::Block(block.clone())),
+            RpnItem::Store(slot) => {
+                let x = stack.pop().ok_or("invalid syntax: too few operands")?;
+                if locals.len() <= slot {
+                    locals.resize(slot + 1, None);
+                }
+                locals[slot] = Some(x);
+            }
+            RpnItem::Load(slot) => match locals.get(slot) {
+                Some(Some(x)) => stack.push(x.clone()),
+                _ => return Err("invalid syntax: unbound local".into()),
+            },
+            RpnItem::Call(ref name) => self.call_word(name, stack)?,
+        }
+        Ok(())
+    }
+
+    /// Applies `op` to the top of `stack`, in the word size and IEEE mode set
+    fn operate(&self, op: MathOperator, stack: &mut Vec<Value>) -> Result<(), String> {
+        // kept to name them in the error if the result isn't finite
+        let operands = match self.strict_ieee {
+            true => stack[stack.len().saturating_sub(op.arity())..].to_vec(),
+            false => Vec::new(),
+        };
+        op.rpn_exec(stack)?;
+        let x = stack.pop().unwrap();
+        if self.strict_ieee {
+            op.check_finite(&operands, &x)?;
         }
+        stack.push(self.wrap(x)?);
+        Ok(())
     }
-    if stack.len() == 1 {
-        Ok(stack[0])
-    } else {
-        Err("invalid syntax: too many operands".to_string())
+
+    /// Gives the top of `stack` the unit of a [`RpnItem::Unit`], or converts it
+    /// for a [`RpnItem::Convert`], in the IEEE mode set
+    fn apply_unit(&self, item: &RpnItem, stack: &mut Vec<Value>) -> Result<(), String> {
+        let x = stack.pop().ok_or("invalid syntax: too few operands")?;
+        // kept to name it in the error if the result isn't finite
+        let operand = self.strict_ieee.then(|| x.clone());
+        let result = match item {
+            RpnItem::Unit(unit) => x.with_unit(unit)?,
+            RpnItem::Convert(unit) => x.convert_to(unit)?,
+            _ => unreachable!(),
+        };
+        if let Some(x) = operand {
+            math::check_finite_unit(&x, &result, &item.to_string())?;
+        }
+        stack.push(result);
+        Ok(())
+    }
+
+    /// Runs a block with `args` on its own stack, returning the one value it leaves
+    fn call(
+        &self,
+        block: &[RpnItem],
+        args: Vec<Value>,
+        locals: &mut Vec<Option<Value>>,
+    ) -> Result<Value, String> {
+        let mut stack = args;
+        self.run(block, &mut stack, locals)?;
+        match stack.len() {
+            1 => Ok(stack.pop().unwrap()),
+            0 => Err("invalid syntax: too few operands".into()),
+            _ => Err("invalid syntax: too many operands".into()),
+        }
     }
 }
 
 pub fn parse_rpn(s: &str) -> Result<Vec<RpnItem>, String> {
-    s.split_whitespace().map(|token| token.parse()).collect()
+    parse_rpn_with_offsets(s)
+        .map(|(items, _)| items)
+        .map_err(|e| e.message)
+}
+
+/// Like [`parse_rpn`], also returning the byte offset in `s` of the token each
+/// item comes from, and reporting errors at the token they're about
+pub fn parse_rpn_with_offsets(s: &str) -> Result<(Vec<RpnItem>, Vec<usize>), LineError> {
+    let (items, sources) =
+        parse_items(&mut split_tokens(s).into_iter(), None).map_err(|(message, token)| {
+            LineError {
+                message,
+                offset: offset_in(s, token),
+            }
+        })?;
+    let offsets = sources.iter().map(|token| offset_in(s, token)).collect();
+    Ok((items, offsets))
+}
+
+/// Words like `hypot` and `clamp` written in RPN, which the binary defines
+/// at startup
+pub const PRELUDE: &str = include_str!("prelude.rpn");
+
+/// The file included by a line like `"geom.rpn" include`, if it's an include
+pub fn parse_include(s: &str) -> Option<String> {
+    match parse_rpn(s).ok()?.as_slice() {
+        [RpnItem::String(path), RpnItem::Call(include)] if include == "include" => {
+            Some(path.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Whether `s` goes on on the next line, because it has unclosed brackets or
+/// braces, or a definition without its `;`
+pub fn is_incomplete(s: &str) -> bool {
+    let mut open = Vec::new();
+    for token in split_tokens(s) {
+        match token {
+            "[" | "{" | ":" => open.push(token),
+            "]" | "}" => {
+                open.pop();
+            }
+            ";" if open.last() == Some(&":") => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    !open.is_empty()
+}
+
+/// Items and the tokens they come from, or an error and the token it's about
+type Parsed<'a> = Result<(Vec<RpnItem>, Vec<&'a str>), (String, &'a str)>;
+
+/// Parses items until the end of the input, or until the end of what `open`
+/// starts, which is the `}` of a block or the `;` of a definition like
+/// `: sq dup * ;`. Also returns the token each item comes from, and fails with
+/// the token the error is about.
+fn parse_items<'a>(
+    tokens: &mut (impl Iterator<Item = &'a str> + Clone),
+    open: Option<&'a str>,
+) -> Parsed<'a> {
+    let end = open.map(|open| if open == "{" { "}" } else { ";" });
+    let mut items = Vec::new();
+    let mut sources = Vec::new();
+    // the lists opened so far, whose `;` end matrix rows instead
+    let mut lists = 0;
+    while let Some(token) = tokens.next() {
+        match token {
+            "in" | "to" => {
+                let unit = tokens
+                    .next()
+                    .ok_or_else(|| (format!("Missing Unit after {}", token), token))?;
+                items.push(RpnItem::Convert(unit.parse().map_err(|e| (e, unit))?));
+            }
+            "{" => items.push(RpnItem::Block(parse_items(tokens, Some(token))?.0)),
+            ":" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| ("Missing Name after :".to_string(), token))?;
+                items.push(RpnItem::Block(parse_items(tokens, Some(token))?.0));
+                items.push(RpnItem::String(name.into()));
+                items.push(RpnItem::Builtin(Builtin::Def));
+            }
+            "[" => match interval_literal(tokens.clone()) {
+                Some((lo, hi)) => {
+                    tokens.nth(3);
+                    items.push(RpnItem::Operand(lo));
+                    items.push(RpnItem::Operand(hi));
+                    items.push(RpnItem::Operator(MathOperator::Interval));
+                }
+                None => {
+                    lists += 1;
+                    items.push(RpnItem::ListStart);
+                }
+            },
+            "]" => {
+                lists -= (lists > 0) as usize;
+                items.push(RpnItem::ListEnd);
+            }
+            "}" if end == Some("}") => return Ok((items, sources)),
+            "}" => return Err(("Mismatched Braces".into(), token)),
+            ";" if end == Some(";") && lists == 0 => return Ok((items, sources)),
+            _ => items.push(token.parse().map_err(|e| (e, token))?),
+        }
+        sources.resize(items.len(), token);
+    }
+    match (open, end) {
+        (None, _) => Ok((items, sources)),
+        (Some(open), Some("}")) => Err(("Mismatched Braces".into(), open)),
+        (Some(open), _) => Err(("Missing ; after :".into(), open)),
+    }
+}
+
+/// Reads the rest of an interval literal like `[1.9 .. 2.1]` after its `[`,
+/// widening the bounds to contain the exact decimal numbers
+fn interval_literal<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<Bounds> {
+    let lo = parse_number(tokens.next()?)?;
+    if tokens.next()? != ".." {
+        return None;
+    }
+    let hi = parse_number(tokens.next()?)?;
+    if tokens.next()? != "]" || lo > hi {
+        return None;
+    }
+    Some((interval::from_literal(lo).0, interval::from_literal(hi).1))
 }
 
 mod test {
@@ -145,4 +1128,610 @@ mod test {
             Value::Number(39.25)
         );
     }
+
+    #[test]
+    fn test_execute_rpn_units() {
+        assert_eq!(
+            execute_rpn(&parse_rpn("3 m 2 s /").unwrap()).unwrap(),
+            Value::Quantity(1.5, "m/s".parse().unwrap())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("36 km/h in m/s").unwrap()).unwrap(),
+            Value::Quantity(10.0, "m/s".parse().unwrap())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("1 km 500 m + to m").unwrap()).unwrap(),
+            Value::Quantity(1500.0, "m".parse().unwrap())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("2 km 500 m /").unwrap()).unwrap(),
+            Value::Number(4.0)
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("1 m 1 s +").unwrap()),
+            Err("invalid type: +".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("1 m in s").unwrap()),
+            Err("invalid type: in s".to_string())
+        );
+        assert!(parse_rpn("1 m in").is_err());
+        assert_eq!(
+            execute_rpn(&parse_rpn("1 m^100 1 m^100 *").unwrap()),
+            Err("overflow: unit exponent".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("1 m 256 ^ 5 +").unwrap()),
+            Err("overflow: unit exponent".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rpn_strings() {
+        assert_eq!(
+            parse_rpn(r#""hello world" "say \"hi\"" ++"#).unwrap(),
+            vec![
+                RpnItem::String("hello world".into()),
+                RpnItem::String("say \"hi\"".into()),
+                RpnItem::Operator(MathOperator::Concat),
+            ]
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn(r#""total: " 1 2 + str ++"#).unwrap()).unwrap(),
+            Value::String("total: 3".into())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn(r#""Hello" 1 3 substr upper"#).unwrap()).unwrap(),
+            Value::String("ELL".into())
+        );
+        assert!(parse_rpn(r#""unterminated"#).is_err());
+        assert!(parse_rpn(r#""a"b""#).is_err());
+    }
+
+    #[test]
+    fn test_rpn_lists() {
+        assert_eq!(
+            parse_rpn("[1 2]{ 2 * }map").unwrap(),
+            vec![
+                RpnItem::ListStart,
+                RpnItem::Operand(1.0),
+                RpnItem::Operand(2.0),
+                RpnItem::ListEnd,
+                RpnItem::Block(vec![
+                    RpnItem::Operand(2.0),
+                    RpnItem::Operator(MathOperator::Mul)
+                ]),
+                RpnItem::Builtin(Builtin::Map),
+            ]
+        );
+        let list = |xs: &[f64]| Value::List(xs.iter().map(|x| Value::Number(*x)).collect());
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2 3] [10 20 30] +").unwrap()).unwrap(),
+            list(&[11.0, 22.0, 33.0])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 1 + 3] 2 ^").unwrap()).unwrap(),
+            list(&[4.0, 9.0])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("1 5 range { 2 * } map").unwrap()).unwrap(),
+            list(&[2.0, 4.0, 6.0, 8.0])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("0 1e20 range").unwrap()),
+            Err("invalid size: range".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("0 inf range").unwrap()),
+            Err("invalid size: range".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[3 1 4 1 5] { 2 > } filter sort reverse").unwrap()).unwrap(),
+            list(&[5.0, 4.0, 3.0])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2 3 4] 0 { + } fold").unwrap()).unwrap(),
+            Value::Number(10.0)
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2] [3] concat -1 nth").unwrap()).unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2] [1 2 3] +").unwrap()),
+            Err("invalid length: +".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2] 2 nth").unwrap()),
+            Err("invalid index: nth".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2] { 1 + } filter").unwrap()),
+            Err("invalid type: filter".to_string())
+        );
+        assert!(execute_rpn(&parse_rpn("1 2 ]").unwrap()).is_err());
+        assert!(parse_rpn("{ 1").is_err());
+        assert!(parse_rpn("1 }").is_err());
+    }
+
+    #[test]
+    fn test_rpn_matrices() {
+        let matrix = |rows: &[&[f64]]| {
+            Value::Matrix(Matrix::from_rows(rows.iter().map(|row| row.to_vec()).collect()).unwrap())
+        };
+        let list = |xs: &[f64]| Value::List(xs.iter().map(|x| Value::Number(*x)).collect());
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2; 3 4]").unwrap()).unwrap(),
+            matrix(&[&[1.0, 2.0], &[3.0, 4.0]])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2 3 ;]").unwrap()).unwrap(),
+            matrix(&[&[1.0, 2.0, 3.0]])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2; 3 4] [5 6; 7 8] *").unwrap()).unwrap(),
+            matrix(&[&[19.0, 22.0], &[43.0, 50.0]])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[2 0; 0 4] [2 8] solve").unwrap()).unwrap(),
+            list(&[1.0, 2.0])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[2 0; 0 4] [1 2] *").unwrap()).unwrap(),
+            list(&[2.0, 8.0])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2; 3 4] transpose det").unwrap()).unwrap(),
+            Value::Number(-2.0)
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[2 0; 0 4] inv").unwrap()).unwrap(),
+            matrix(&[&[0.5, 0.0], &[0.0, 0.25]])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2 3] [4 5 6] dot").unwrap()).unwrap(),
+            Value::Number(32.0)
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 0 0] [0 1 0] cross").unwrap()).unwrap(),
+            list(&[0.0, 0.0, 1.0])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2; 3]").unwrap()),
+            Err("invalid shape: matrix".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2; 3 4] [1 2 3 ;] *").unwrap()),
+            Err("invalid shape: *".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2; 2 4] inv").unwrap()),
+            Err("singular matrix: inv".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2] [1 2] cross").unwrap()),
+            Err("invalid shape: cross".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("1e20 identity").unwrap()),
+            Err("invalid size: identity".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 0; 0 1] 1e9 ^").unwrap()).unwrap(),
+            matrix(&[&[1.0, 0.0], &[0.0, 1.0]])
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 2; 3 4] 1e20 ^").unwrap()),
+            Err("invalid size: ^".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rpn_intervals() {
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 .. 2] [3 .. 5] -").unwrap()),
+            Ok(Value::Interval(-4.0, -1.0))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("3 1 ± 2 *").unwrap()),
+            Ok(Value::Interval(4.0, 8.0))
+        );
+        match execute_rpn(&parse_rpn("[1.9 .. 2.1]").unwrap()) {
+            Ok(Value::Interval(lo, hi)) => assert!(lo < 1.9 && hi > 2.1),
+            x => panic!("not an interval: {:?}", x),
+        }
+        assert_eq!(
+            execute_rpn(&parse_rpn("1 [0 .. 2] /").unwrap()),
+            Ok(Value::Interval(0.5, f64::INFINITY))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 .. 2] 3 <").unwrap()),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 .. 2] 3 =").unwrap()),
+            Ok(Value::Boolean(false))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 .. 3] 2 <").unwrap()),
+            Err("ambiguous comparison: <".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[-2 .. -1] sqrt").unwrap()),
+            Err("invalid domain: sqrt".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("2 1 ..").unwrap()),
+            Err("invalid type: ..".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 .. 2] fact").unwrap()),
+            Err("unsupported for interval: fact".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("[1 .. 2] 3 m *").unwrap()),
+            Err("unsupported for interval: *".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rpn_measurements() {
+        assert_eq!(
+            execute_rpn(&parse_rpn("9.81 0.02 +/-").unwrap()),
+            Ok(Value::Measurement(9.81, 0.02))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("10 3 +/- 20 4 +/- +").unwrap()),
+            Ok(Value::Measurement(30.0, 5.0))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("2 0.5 +/- 4 *").unwrap()),
+            Ok(Value::Measurement(8.0, 2.0))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("16 2 +/- sqrt").unwrap()),
+            Ok(Value::Measurement(4.0, 0.25))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("2 1 +/- 3 >").unwrap()),
+            Ok(Value::Boolean(false))
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("2 1 +/- \"a\" ++").unwrap()),
+            Err("invalid type: ++".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("5 1 +/- fact").unwrap()),
+            Err("unsupported for measurement: fact".to_string())
+        );
+        assert_eq!(
+            execute_rpn(&parse_rpn("2 1 +/- 3 m *").unwrap()),
+            Err("unsupported for measurement: *".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rpn_dates() {
+        let eval = |s: &str| execute_rpn(&parse_rpn(s).unwrap()).map(|v| v.to_string());
+        assert_eq!(
+            eval("2026-10-17 PT3H30M +"),
+            Ok("2026-10-17T03:30:00Z".into())
+        );
+        assert_eq!(eval("2026-10-17 P1D -"), Ok("2026-10-16".into()));
+        assert_eq!(eval("2026-12-25 2026-10-17 -"), Ok("P69D".into()));
+        assert_eq!(eval("2026-10-17 weekday"), Ok("Saturday".into()));
+        assert_eq!(eval("PT1H 4 / PT10M +"), Ok("PT25M".into()));
+        assert_eq!(eval("PT90M in h"), Ok("1.5 h".into()));
+        assert_eq!(eval("PT1H 30 min >"), Ok("true".into()));
+        assert_eq!(
+            eval("2026-10-17T22:00Z \"-05:00\" tz \"%Y-%m-%d %H:%M %z\" format"),
+            Ok("2026-10-17 17:00 -0500".into())
+        );
+        assert_eq!(
+            eval("2026-10-17 2026-10-18 +"),
+            Err("invalid type: +".into())
+        );
+        assert_eq!(
+            eval("2026-10-17 \"+25:00\" tz"),
+            Err("invalid offset: \"+25:00\"".into())
+        );
+        assert!(parse_rpn("2026-02-30").is_err());
+        assert_eq!(eval("2026-10-17 1e300 s +"), Err("out of range: +".into()));
+        assert_eq!(eval("2026-10-17 1e20 s -"), Err("out of range: -".into()));
+        assert_eq!(eval("9999-12-31 P1D +"), Err("out of range: +".into()));
+        assert_eq!(eval("PT1H 1e300 *"), Err("out of range: *".into()));
+        assert_eq!(eval("PT1H 0 /"), Err("out of range: /".into()));
+    }
+
+    #[test]
+    fn test_rpn_bitwise() {
+        let eval = |s: &str| execute_rpn(&parse_rpn(s).unwrap());
+        assert_eq!(eval("0xFF 0x0F and"), Ok(Value::Number(15.0)));
+        assert_eq!(eval("0b1010 0b0101 or"), Ok(Value::Number(15.0)));
+        assert_eq!(eval("0o17 1 xor"), Ok(Value::Number(14.0)));
+        assert_eq!(eval("0 not"), Ok(Value::Number(-1.0)));
+        assert_eq!(eval("1 10 shl"), Ok(Value::Number(1024.0)));
+        assert_eq!(eval("-8 1 shr"), Ok(Value::Number(-4.0)));
+        assert_eq!(eval("1 64 shl"), Ok(Value::Number(0.0)));
+        assert_eq!(eval("-1_000 popcount"), Ok(Value::Number(56.0)));
+        assert_eq!(eval("0x100 clz 0x100 ctz -"), Ok(Value::Number(47.0)));
+        assert_eq!(eval("1.5 1 and"), Err("invalid type: and".into()));
+        assert_eq!(eval("1 -1 shl"), Err("invalid shift: shl".into()));
+        assert_eq!(eval("true false and"), Err("invalid type: and".into()));
+        assert_eq!(parse_rpn("0xZZ"), Err("Invalid Number: 0xZZ".into()));
+    }
+
+    #[test]
+    fn test_rpn_ifte() {
+        let eval = |s: &str| execute_rpn(&parse_rpn(s).unwrap());
+        assert_eq!(
+            eval("3 0 != { 1 3 / } { 0 } ifte 3 *"),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(eval("0 0 != { 1 0 / } { 0 } ifte"), Ok(Value::Number(0.0)));
+        assert_eq!(eval("false 1 2 ifte"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            eval("2 true { 3 * } { \"a\" + } ifte"),
+            Ok(Value::Number(6.0))
+        );
+        assert_eq!(eval("1 { 2 } { 3 } ifte"), Err("invalid type: ifte".into()));
+        assert_eq!(eval("false { 1 } andthen"), Ok(Value::Boolean(false)));
+        assert_eq!(eval("true { 1 2 < } andthen"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("true { 1 } orelse"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("false false orelse"), Ok(Value::Boolean(false)));
+        assert_eq!(
+            eval("false { 1 } orelse"),
+            Err("invalid type: orelse".into())
+        );
+        assert_eq!(
+            eval("{ 2 } { 3 } ifte"),
+            Err("invalid syntax: too few operands".into())
+        );
+    }
+
+    #[test]
+    fn test_rpn_words() {
+        let interpreter = Interpreter::default();
+        let eval = |s: &str| interpreter.execute_line(&parse_rpn(s)?);
+        assert_eq!(eval("1 2 swap -"), Ok(Some(Value::Number(1.0))));
+        assert_eq!(eval("1 2 over + +"), Ok(Some(Value::Number(4.0))));
+        assert_eq!(eval("1 2 drop"), Ok(Some(Value::Number(1.0))));
+        assert_eq!(eval("1 2 3 rot - -"), Ok(Some(Value::Number(0.0))));
+        assert_eq!(eval("3 { dup * } exec"), Ok(Some(Value::Number(9.0))));
+        assert_eq!(eval(": sq dup * ;"), Ok(None));
+        assert_eq!(eval("3 sq"), Ok(Some(Value::Number(9.0))));
+        assert_eq!(
+            eval("{ sq sq } 'quad def 2 quad"),
+            Ok(Some(Value::Number(16.0)))
+        );
+        assert_eq!(
+            eval(": mat [1 2; 3 4] ; mat mat ="),
+            Ok(Some(Value::Boolean(true)))
+        );
+        assert_eq!(
+            eval(": down dup 0 > { 1 - down } { } ifte ; 5 down"),
+            Ok(Some(Value::Number(0.0)))
+        );
+        assert_eq!(interpreter.words.borrow()["sq"].source, ": sq dup * ;");
+        assert!(interpreter.warnings.borrow().is_empty());
+        assert_eq!(eval(": sq 2 ^ ; 3 sq"), Ok(Some(Value::Number(9.0))));
+        assert_eq!(
+            interpreter.warnings.take(),
+            vec!["redefining sq".to_string()]
+        );
+        assert_eq!(eval("1 cube"), Err("unknown word: cube".into()));
+        assert_eq!(eval("{ 1 } 'h def"), Err("invalid name: h".into()));
+        assert_eq!(eval("{ 1 } 2 def"), Err("invalid type: def".into()));
+        assert_eq!(eval("1 exec"), Err("invalid type: exec".into()));
+        assert_eq!(eval("dup"), Err("invalid syntax: too few operands".into()));
+        assert_eq!(
+            eval("{ dup exec } dup exec"),
+            Err("recursion too deep: exec".into())
+        );
+        assert_eq!(eval(": sq dup *"), Err("Missing ; after :".into()));
+        assert_eq!(eval("1 +-"), Err("Invalid Operator: +-".into()));
+    }
+
+    #[test]
+    fn test_rpn_loops() {
+        let eval = |s: &str| execute_rpn(&parse_rpn(s).unwrap());
+        assert_eq!(eval("1 10 { 2 * } times"), Ok(Value::Number(1024.0)));
+        assert_eq!(eval("1 0 { 2 * } times"), Ok(Value::Number(1.0)));
+        assert_eq!(
+            eval("1 { dup 100 < } { 2 * } while"),
+            Ok(Value::Number(128.0))
+        );
+        assert_eq!(eval("0 1 10 1 { + } for"), Ok(Value::Number(55.0)));
+        assert_eq!(eval("0 1 0 -0.25 { + } for"), Ok(Value::Number(2.5)));
+        assert_eq!(eval("0 0 0.3 0.1 { drop 1 + } for"), Ok(Value::Number(4.0)));
+        assert_eq!(eval("0 [1 2 3] { + } each"), Ok(Value::Number(6.0)));
+        assert_eq!(eval("1 1.5 { } times"), Err("invalid count: times".into()));
+        assert_eq!(eval("1 1 2 0 { } for"), Err("invalid step: for".into()));
+        assert_eq!(eval("1 { 1 } { } while"), Err("invalid type: while".into()));
+        assert_eq!(eval("1 2 { } each"), Err("invalid type: each".into()));
+        assert_eq!(
+            eval("{ true } { } while"),
+            Err(format!("too many steps: {}", MAX_STEPS))
+        );
+        assert_eq!(
+            eval("1e15 { } times"),
+            Err(format!("too many steps: {}", MAX_STEPS))
+        );
+    }
+
+    #[test]
+    fn test_rpn_parse_include() {
+        assert_eq!(
+            parse_include("\"geom.rpn\" include"),
+            Some("geom.rpn".into())
+        );
+        assert_eq!(parse_include("\"geom.rpn\" len"), None);
+        assert_eq!(parse_include("include"), None);
+    }
+
+    #[test]
+    fn test_rpn_try() {
+        let eval = |s: &str| execute_rpn(&parse_rpn(s).unwrap());
+        let string = |s: &str| Ok(Value::String(s.into()));
+        assert_eq!(
+            eval("{ \"a\" 1 + } { errkind } try"),
+            string("invalid type")
+        );
+        assert_eq!(eval("{ \"a\" 1 + } { errmsg } try"), string("+"));
+        // the stack is put back as it was before the block failed
+        assert_eq!(
+            eval("1 2 { drop drop \"a\" 1 + } { drop + } try"),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(eval("1 { 2 + } { drop 0 } try"), Ok(Value::Number(3.0)));
+        assert_eq!(
+            eval("{ \"bad input: negative\" throw } { errkind } try"),
+            string("bad input")
+        );
+        assert_eq!(
+            eval("{ \"oops\" throw } { } try"),
+            Ok(Value::Error("error".into(), "oops".into()))
+        );
+        assert_eq!(
+            eval("{ { \"a: b\" throw } { throw } try } { errmsg } try"),
+            string("b")
+        );
+        assert_eq!(eval("\"boom\" throw"), Err("boom".into()));
+        assert_eq!(eval("1 throw"), Err("invalid type: throw".into()));
+        assert_eq!(eval("1 { 2 } try"), Err("invalid type: try".into()));
+        assert_eq!(eval("1 errkind"), Err("invalid type: errkind".into()));
+    }
+
+    #[test]
+    fn test_rpn_strict_ieee() {
+        let eval = |strict_ieee, s: &str| {
+            let interpreter = Interpreter {
+                strict_ieee,
+                ..Default::default()
+            };
+            interpreter.execute(&parse_rpn(s).unwrap())
+        };
+        assert_eq!(eval(false, "1 0 /"), Ok(Value::Number(f64::INFINITY)));
+        assert_eq!(eval(true, "1 0 /"), Err("division by zero: 1 / 0".into()));
+        assert_eq!(
+            eval(true, "[1 2] 0 /"),
+            Err("division by zero: [1, 2] / 0".into())
+        );
+        assert_eq!(
+            eval(true, "-1 sqrt"),
+            Err("invalid domain: sqrt(-1)".into())
+        );
+        assert_eq!(eval(true, "10 1000 ^"), Err("overflow: 10 ^ 1000".into()));
+        assert_eq!(eval(true, "0 -1 ^"), Err("division by zero: 0 ^ -1".into()));
+        assert_eq!(
+            eval(true, "[1 .. 2] [-1 .. 1] /"),
+            Err("division by zero: [1 .. 2] / [-1 .. 1]".into())
+        );
+        assert_eq!(
+            eval(true, "[1 .. 2] [0 .. 1] /"),
+            Err("division by zero: [1 .. 2] / [0 .. 1]".into())
+        );
+        assert_eq!(eval(true, "1e308 10 *"), Err("overflow: 1e308 * 10".into()));
+        assert_eq!(
+            eval(true, "1e308 km in m"),
+            Err("overflow: 1e308 km in m".into())
+        );
+        assert_eq!(
+            eval(true, "1e308 km m^-1"),
+            Err("overflow: 1e308 km 1/m".into())
+        );
+        assert_eq!(
+            eval(true, "[1 0 0] { 1 swap / } map"),
+            Err("division by zero: 1 / 0".into())
+        );
+        // only infinities and NaNs an operator makes are errors
+        assert_eq!(eval(true, "inf 1 +"), Ok(Value::Number(f64::INFINITY)));
+        assert_eq!(
+            eval(true, "inf km in m"),
+            Ok(Value::Quantity(f64::INFINITY, "m".parse().unwrap()))
+        );
+        assert_eq!(eval(true, "\"inf\" num"), Ok(Value::Number(f64::INFINITY)));
+        assert_eq!(eval(true, "1 4 /"), Ok(Value::Number(0.25)));
+        assert_eq!(
+            eval(true, "{ 1 0 / } { errkind } try"),
+            Ok(Value::String("division by zero".into()))
+        );
+    }
+
+    #[test]
+    fn test_rpn_is_incomplete() {
+        assert!(is_incomplete("[1 2"));
+        assert!(is_incomplete("{ dup *"));
+        assert!(is_incomplete(": sq dup *"));
+        assert!(is_incomplete(": id [1 0; 0 1]"));
+        assert!(!is_incomplete(": sq dup * ;"));
+        assert!(!is_incomplete("[1 2; 3 4] 2 *"));
+        assert!(!is_incomplete("1 2 +"));
+    }
+
+    #[test]
+    fn test_rpn_offsets() {
+        let eval = |s| {
+            let (tokens, offsets) = parse_rpn_with_offsets(s)?;
+            Interpreter::default().execute_line_with_offsets(&tokens, &offsets)
+        };
+        let error = |message: &str, offset| {
+            Err(LineError {
+                message: message.into(),
+                offset,
+            })
+        };
+        assert_eq!(eval("1 2 + foo"), error("unknown word: foo", 6));
+        assert_eq!(eval("1 2 3x +"), error("Invalid Number: 3x", 4));
+        assert_eq!(eval("1 { 2 +"), error("Mismatched Braces", 2));
+        assert_eq!(eval("1 : sq dup *"), error("Missing ; after :", 2));
+        assert_eq!(eval("5 m in furlong"), error("Invalid Unit: furlong", 7));
+        assert_eq!(eval("[1 .. 2] \"a\" +"), error("invalid type: +", 13));
+        assert_eq!(eval("1 [ 2 [ 3 ]"), error("invalid syntax: unmatched [", 2));
+        assert_eq!(eval("1 2"), error("invalid syntax: too many operands", 0));
+        // errors in words are where they're called
+        assert_eq!(
+            eval(": f 1 0 ; 2 f +"),
+            error("invalid syntax: too many operands", 0)
+        );
+        assert_eq!(eval(": bad \"a\" - ; 2 bad"), error("invalid type: -", 16));
+    }
+
+    #[test]
+    fn test_rpn_word_size() {
+        let eval = |size: &str, s: &str| {
+            let interpreter = Interpreter {
+                word_size: Some(size.parse().unwrap()),
+                ..Default::default()
+            };
+            interpreter.execute(&parse_rpn(s).unwrap())
+        };
+        assert_eq!(eval("u8", "200 100 +"), Ok(Value::Integer(44)));
+        assert_eq!(eval("i8", "100 100 +"), Ok(Value::Integer(-56)));
+        assert_eq!(eval("u8", "0xFF 1 +"), Ok(Value::Integer(0)));
+        assert_eq!(eval("i16", "7 2 /"), Ok(Value::Integer(3)));
+        assert_eq!(eval("u16", "0 not"), Ok(Value::Integer(65535)));
+        assert_eq!(eval("u8", "1 clz"), Ok(Value::Integer(7)));
+        assert_eq!(eval("i8", "-1 popcount"), Ok(Value::Integer(8)));
+        assert_eq!(eval("u32", "0 ctz"), Ok(Value::Integer(32)));
+        assert_eq!(
+            eval("u8", "[255 256] 1 +"),
+            Ok(Value::List(vec![Value::Integer(0), Value::Integer(1)]))
+        );
+        assert_eq!(
+            eval("u64", "0xFFFFFFFFFFFFFFFF"),
+            Ok(Value::Integer(u64::MAX as i128))
+        );
+        assert_eq!(eval("u64", "0 1 -"), Ok(Value::Integer(u64::MAX as i128)));
+        assert_eq!(
+            eval("i64", "9223372036854775807 1 +"),
+            Ok(Value::Integer(i64::MIN as i128))
+        );
+        assert_eq!(
+            eval("u64", "0xFFFFFFFFFFFFFFFF 0xFFFFFFFFFFFFFFFE xor"),
+            Ok(Value::Integer(1))
+        );
+        assert_eq!(eval("u64", "1 63 shl clz"), Ok(Value::Integer(0)));
+        assert_eq!(eval("i64", "1e300"), Err("out of range: i64".into()));
+    }
 }
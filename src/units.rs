@@ -0,0 +1,360 @@
+use std::{
+    fmt::Display,
+    ops::{Div, Mul},
+    str::FromStr,
+};
+
+/// Symbols of the SI base units, in the order used by [`Dimension`]
+pub const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// Exponents of the SI base dimensions (length, mass, time, current,
+/// temperature, amount of substance, luminous intensity)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dimension(pub [i8; 7]);
+
+impl Mul for Dimension {
+    type Output = Option<Dimension>;
+
+    /// Multiplying quantities adds the exponents of their dimensions, if they
+    /// still fit
+    fn mul(self, other: Dimension) -> Option<Dimension> {
+        let mut exponents = [0; 7];
+        for (i, exponent) in exponents.iter_mut().enumerate() {
+            *exponent = self.0[i].checked_add(other.0[i])?;
+        }
+        Some(Dimension(exponents))
+    }
+}
+
+impl Dimension {
+    pub const NONE: Dimension = Dimension([0; 7]);
+    pub const TIME: Dimension = Dimension([0, 0, 1, 0, 0, 0, 0]);
+
+    /// Multiplies the exponents by `n`, if they still fit
+    pub fn powi(self, n: i32) -> Option<Dimension> {
+        self.powf(n as f64)
+    }
+
+    /// Multiplies the exponents by `p`, if they're still integers that fit
+    fn powf(self, p: f64) -> Option<Dimension> {
+        let mut exponents = [0; 7];
+        for (exponent, d) in exponents.iter_mut().zip(self.0) {
+            *exponent = exponent_of(d as f64 * p)?;
+        }
+        Some(Dimension(exponents))
+    }
+
+    pub fn is_none(self) -> bool {
+        self == Dimension::NONE
+    }
+}
+
+/// `x` as an `i8` exponent, if it's an integer that fits
+fn exponent_of(x: f64) -> Option<i8> {
+    match x.fract() == 0.0 && x >= i8::MIN as f64 && x <= i8::MAX as f64 {
+        true => Some(x as i8),
+        false => None,
+    }
+}
+
+/// A unit that a number can be measured in, e.g. `km/h`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unit {
+    /// Size of one of this unit in SI base units
+    pub factor: f64,
+    pub dimension: Dimension,
+    /// The named units this unit is made of, with their exponents
+    pub terms: Vec<(String, i32)>,
+}
+
+/// (symbol, factor, dimension, accepts SI prefixes)
+const NAMED_UNITS: &[(&str, f64, [i8; 7], bool)] = &[
+    // base units (the kilogram is prefixed from the gram)
+    ("m", 1.0, [1, 0, 0, 0, 0, 0, 0], true),
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0, 0], true),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    // derived units
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    ("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0, 0], true),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0, 0], true),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", 1.0, [2, 1, -3, -1, 0, 0, 0], true),
+    ("ohm", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    ("Ω", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    ("F", 1.0, [-2, -1, 4, 2, 0, 0, 0], true),
+    ("L", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    ("bar", 1e5, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("eV", 1.602176634e-19, [2, 1, -2, 0, 0, 0, 0], true),
+    // common non-SI units
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("day", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("t", 1e3, [0, 1, 0, 0, 0, 0, 0], false),
+    ("ft", 0.3048, [1, 0, 0, 0, 0, 0, 0], false),
+    ("mi", 1609.344, [1, 0, 0, 0, 0, 0, 0], false),
+    ("lb", 0.45359237, [0, 1, 0, 0, 0, 0, 0], false),
+    ("atm", 101325.0, [-1, 1, -2, 0, 0, 0, 0], false),
+    ("cal", 4.184, [2, 1, -2, 0, 0, 0, 0], false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("µ", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+];
+
+impl Unit {
+    /// The dimensionless unit
+    pub fn one() -> Unit {
+        Unit {
+            factor: 1.0,
+            dimension: Dimension::NONE,
+            terms: Vec::new(),
+        }
+    }
+
+    /// Looks up a single named unit, possibly with an SI prefix
+    fn named(symbol: &str) -> Option<Unit> {
+        let unit = |factor: f64, dims: [i8; 7]| Unit {
+            factor,
+            dimension: Dimension(dims),
+            terms: vec![(symbol.to_string(), 1)],
+        };
+
+        // exact matches win, so `min` is minutes and not milli-inches
+        if let Some(&(_, factor, dims, _)) = NAMED_UNITS.iter().find(|u| u.0 == symbol) {
+            return Some(unit(factor, dims));
+        }
+
+        PREFIXES.iter().find_map(|&(prefix, scale)| {
+            let rest = symbol.strip_prefix(prefix)?;
+            NAMED_UNITS
+                .iter()
+                .find(|u| u.3 && u.0 == rest)
+                .map(|&(_, factor, dims, _)| unit(factor * scale, dims))
+        })
+    }
+
+    /// The SI base unit with the given dimension, e.g. `kg*m/s^2`
+    pub fn base(dimension: Dimension) -> Unit {
+        Unit {
+            factor: 1.0,
+            dimension,
+            terms: BASE_UNITS
+                .iter()
+                .zip(dimension.0)
+                .filter(|(_, d)| *d != 0)
+                .map(|(s, d)| (s.to_string(), d as i32))
+                .collect(),
+        }
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dimension.is_none()
+    }
+
+    /// Raises the unit to an integer power, if its exponents still fit
+    pub fn powi(&self, n: i32) -> Option<Unit> {
+        self.powf(n as f64).ok()
+    }
+
+    /// Raises the unit to a possibly fractional power, which only works if
+    /// every resulting exponent is an integer small enough to fit
+    pub fn powf(&self, p: f64) -> Result<Unit, String> {
+        let is_int = |e: f64| e.fract() == 0.0;
+        let overflow = || "overflow: unit exponent".to_string();
+        if self.dimension.0.iter().any(|d| !is_int(*d as f64 * p)) {
+            return Err("invalid type: ^".into());
+        }
+        let dimension = self.dimension.powf(p).ok_or_else(overflow)?;
+        if p == 0.0 {
+            return Ok(Unit::one());
+        }
+        if self.terms.iter().all(|(_, e)| is_int(*e as f64 * p)) {
+            let terms = self.terms.iter().map(|(s, e)| {
+                let e = *e as f64 * p;
+                match e.abs() <= i32::MAX as f64 {
+                    true => Ok((s.clone(), e as i32)),
+                    false => Err(overflow()),
+                }
+            });
+            return Ok(Unit {
+                factor: self.factor.powf(p),
+                dimension,
+                terms: terms.collect::<Result<_, _>>()?,
+            });
+        }
+        Ok(Unit {
+            factor: self.factor.powf(p),
+            ..Unit::base(dimension)
+        })
+    }
+
+    /// Converts `value` measured in this unit to the unit `to`, if they have
+    /// the same dimension
+    pub fn convert(&self, value: f64, to: &Unit) -> Option<f64> {
+        if self.dimension != to.dimension {
+            return None;
+        }
+        Some(value * self.factor / to.factor)
+    }
+}
+
+impl Mul<&Unit> for &Unit {
+    type Output = Option<Unit>;
+
+    /// Multiplies the units, if the exponents still fit
+    fn mul(self, other: &Unit) -> Option<Unit> {
+        let mut terms = self.terms.clone();
+        for (symbol, exp) in &other.terms {
+            match terms.iter_mut().find(|(s, _)| s == symbol) {
+                Some((_, e)) => *e = e.checked_add(*exp)?,
+                None => terms.push((symbol.clone(), *exp)),
+            }
+        }
+        terms.retain(|(_, e)| *e != 0);
+        Some(Unit {
+            factor: self.factor * other.factor,
+            dimension: (self.dimension * other.dimension)?,
+            terms,
+        })
+    }
+}
+
+impl Div<&Unit> for &Unit {
+    type Output = Option<Unit>;
+
+    fn div(self, other: &Unit) -> Option<Unit> {
+        self * &other.powi(-1)?
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    /// Parses unit expressions like `m`, `km/h` or `kg*m/s^2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut unit = Unit::one();
+        let mut divide = false;
+        let mut rest = s;
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let (term, exp) = match rest[..end].split_once('^') {
+                Some((term, exp)) => (
+                    term,
+                    exp.parse::<i32>()
+                        .map_err(|_| format!("Invalid Unit: {}", s))?,
+                ),
+                None => (&rest[..end], 1),
+            };
+            let invalid = || format!("Invalid Unit: {}", s);
+            let named = Unit::named(term).ok_or_else(invalid)?;
+            let named = named
+                .powi(if divide {
+                    exp.checked_neg().ok_or_else(invalid)?
+                } else {
+                    exp
+                })
+                .ok_or_else(invalid)?;
+            unit = (&unit * &named).ok_or_else(invalid)?;
+
+            if end == rest.len() {
+                break;
+            }
+            divide = rest[end..].starts_with('/');
+            rest = &rest[end + 1..];
+        }
+        Ok(unit)
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let term = |(symbol, exp): &(String, i32)| match exp.abs() {
+            1 => symbol.clone(),
+            e => format!("{}^{}", symbol, e),
+        };
+        let num = self
+            .terms
+            .iter()
+            .filter(|(_, e)| *e > 0)
+            .map(term)
+            .collect::<Vec<_>>();
+        let den = self
+            .terms
+            .iter()
+            .filter(|(_, e)| *e < 0)
+            .map(term)
+            .collect::<Vec<_>>();
+
+        if num.is_empty() {
+            write!(f, "1")?;
+        } else {
+            write!(f, "{}", num.join("*"))?;
+        }
+        for d in den {
+            write!(f, "/{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_unit_from_str() {
+        let km: Unit = "km".parse().unwrap();
+        assert_eq!(km.factor, 1000.0);
+        assert_eq!(km.dimension, Dimension([1, 0, 0, 0, 0, 0, 0]));
+
+        let newton: Unit = "kg*m/s^2".parse().unwrap();
+        assert_eq!(newton.dimension, "N".parse::<Unit>().unwrap().dimension);
+        assert_eq!(newton.to_string(), "kg*m/s^2");
+
+        assert_eq!("min".parse::<Unit>().unwrap().factor, 60.0);
+        assert_eq!("ms".parse::<Unit>().unwrap().factor, 1e-3);
+        assert!("kmin".parse::<Unit>().is_err());
+        assert!("foo".parse::<Unit>().is_err());
+        assert!("m/".parse::<Unit>().is_err());
+    }
+
+    #[test]
+    fn test_unit_arithmetic() {
+        let m: Unit = "m".parse().unwrap();
+        let s: Unit = "s".parse().unwrap();
+        assert_eq!((&m / &s).unwrap().to_string(), "m/s");
+        assert_eq!((&m * &m).unwrap().to_string(), "m^2");
+        assert_eq!(&m / &m, Some(Unit::one()));
+        assert_eq!(m.powi(2).unwrap().powf(0.5), Ok(m.clone()));
+        assert_eq!(m.powf(0.5), Err("invalid type: ^".to_string()));
+        assert_eq!(m.powi(127).unwrap().dimension.0[0], 127);
+        assert_eq!(m.powi(128), None);
+        assert_eq!(m.powf(256.0), Err("overflow: unit exponent".to_string()));
+        let m100 = m.powi(100).unwrap();
+        assert_eq!(&m100 * &m100, None);
+        assert!("m^200".parse::<Unit>().is_err());
+
+        let kmh: Unit = "km/h".parse().unwrap();
+        let ms: Unit = "m/s".parse().unwrap();
+        assert_eq!(kmh.convert(36.0, &ms), Some(10.0));
+        assert_eq!(kmh.convert(36.0, &m), None);
+    }
+}
@@ -1,41 +1,382 @@
-/// Adds whitespace between numbers and operators or parentheses
+use std::fmt::Display;
+
+use crate::{datetime, math};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Space,
+    Number,
+    Identifier,
+    Operator,
+    /// Characters that are always a token on their own, like parentheses
+    Delimiter,
+}
+
+/// Adds whitespace between numbers, identifiers, operators and delimiters
+/// (parentheses, brackets, braces, commas, semicolons, `?` and `:`)
 pub fn normalize_whitespace(s: &str) -> String {
+    normalize_whitespace_with_offsets(s).0
+}
+
+/// Like [`normalize_whitespace`], also returning the offset in `s` of each
+/// byte of the result, so tokens can be traced back to where they are in `s`
+pub fn normalize_whitespace_with_offsets(s: &str) -> (String, Vec<usize>) {
     let mut result = String::new();
-    let mut last_was_number = false;
-    let mut last_was_operator = false;
-    let mut last_was_parenthesis = false;
-    for c in s.chars() {
-        if c.is_whitespace() {
-            result.push(' ');
+    let mut offsets = Vec::new();
+    let mut push = |text: &str, offset: usize| {
+        result.push_str(text);
+        offsets.extend(offset..offset + text.len());
+    };
+    let mut last = CharClass::Space;
+    let mut last_char = ' ';
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let offset = s.len() - chars.as_str().len() - c.len_utf8();
+        // `..` is an operator even between numbers, as in `1..2`, and `+/-`
+        // is one operator even though `-` usually starts a new one
+        let operator = match c {
+            '.' if chars.as_str().starts_with('.') => Some(".."),
+            '+' if chars.as_str().starts_with("/-") => Some("+/-"),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            chars.nth(operator.len() - 2);
+            if last != CharClass::Space {
+                push(" ", offset);
+            }
+            push(operator, offset);
+            last = CharClass::Operator;
+            continue;
+        }
+        if last != CharClass::Number && last != CharClass::Identifier {
+            // literals like `0xFF`, `2026-10-17`, `PT3H` and `1.5e-3` are
+            // copied as they are
+            let rest = &s[offset..];
+            let literal = radix_literal_len(rest)
+                .or_else(|| datetime::literal_len(rest))
+                .map(|len| (len, CharClass::Identifier))
+                .or_else(|| number_literal_len(rest).map(|len| (len, CharClass::Number)));
+            if let Some((len, class)) = literal {
+                if last != CharClass::Space {
+                    push(" ", offset);
+                }
+                push(&rest[..len], offset);
+                chars = rest[len..].chars();
+                last = class;
+                continue;
+            }
+        }
+        if c == '"' {
+            // string literals are copied as they are
+            if last != CharClass::Space {
+                push(" ", offset);
+            }
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => break,
+                    _ => {}
+                }
+            }
+            push(&s[offset..s.len() - chars.as_str().len()], offset);
+            last = CharClass::Delimiter;
             continue;
         }
-        if c.is_digit(10) || c == '.' {
-            if last_was_operator || last_was_parenthesis {
-                result.push(' ');
-            }
-            result.push(c);
-            last_was_number = true;
-            last_was_operator = false;
-            last_was_parenthesis = false;
-        } else if c == '(' || c == ')' {
-            if last_was_number | last_was_operator | last_was_parenthesis {
-                result.push(' ');
-            }
-            result.push(c);
-            last_was_number = false;
-            last_was_operator = false;
-            last_was_parenthesis = true;
+
+        let class = if c.is_whitespace() {
+            CharClass::Space
+        } else if "%²³".contains(c) {
+            // postfix operators, which end what's before them
+            CharClass::Delimiter
+        } else if last == CharClass::Identifier && (c.is_alphanumeric() || c == '_') {
+            CharClass::Identifier
+        } else if last == CharClass::Number
+            && c == '_'
+            && chars.as_str().starts_with(|c: char| c.is_ascii_digit())
+        {
+            // digit separators, as in `1_000`
+            CharClass::Number
+        } else if c.is_ascii_digit() || c == '.' {
+            CharClass::Number
+        } else if c.is_alphabetic() || c == '_' {
+            CharClass::Identifier
+        } else if "()[]{},;?:".contains(c) {
+            CharClass::Delimiter
+        } else {
+            CharClass::Operator
+        };
+        // a `-` always starts a new operator, so `2*-3` is `2 * -3`, and so
+        // does `!` and what comes after it, so `3!+1` is `3 ! + 1`, except in
+        // `!=`
+        let split = class != last
+            || class == CharClass::Delimiter
+            || c == '-'
+            || c == '!'
+            || (last_char == '!' && c != '=');
+        if split && last != CharClass::Space && class != CharClass::Space {
+            push(" ", offset);
+        }
+        match class {
+            CharClass::Space => push(" ", offset),
+            _ => push(&s[offset..offset + c.len_utf8()], offset),
+        }
+        last = class;
+        last_char = c;
+    }
+    (result, offsets)
+}
+
+/// Length of the `0x`, `0o` or `0b` literal at the start of `s`, if there's
+/// one. Its points are included, so malformed numbers like `0x1.8` are one
+/// token and can be reported whole.
+fn radix_literal_len(s: &str) -> Option<usize> {
+    if !["0x", "0o", "0b"]
+        .iter()
+        .any(|prefix| s.starts_with(prefix))
+    {
+        return None;
+    }
+    let bytes = s.as_bytes();
+    let mut len = 2;
+    // but not `..`, as in `0x1..0xF`
+    while bytes.get(len).is_some_and(|&b| {
+        b.is_ascii_alphanumeric() || b == b'_' || (b == b'.' && bytes.get(len + 1) != Some(&b'.'))
+    }) {
+        len += 1;
+    }
+    Some(len)
+}
+
+/// Length of the decimal number at the start of `s`, like `1_000`, `.5` or
+/// `1.5e-3`, if there's one. A point, `_` or second exponent right after it
+/// makes the rest of the word part of it, so malformed numbers like `1.2.3`,
+/// `1__0` and `2e3e4` are one token and can be reported whole, and so is an
+/// exponent without digits, like `2e` in `2e + 1`.
+fn number_literal_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let is_digit = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_digit);
+    let digits = |mut i: usize| {
+        while is_digit(i)
+            || (i > 0
+                && bytes[i - 1].is_ascii_digit()
+                && bytes.get(i) == Some(&b'_')
+                && is_digit(i + 1))
+        {
+            i += 1;
+        }
+        i
+    };
+    let is_point = |i: usize| bytes.get(i) == Some(&b'.') && bytes.get(i + 1) != Some(&b'.');
+    let exponent_sign = |i: usize| matches!(bytes.get(i + 1), Some(b'+' | b'-')) as usize;
+    let is_exponent =
+        |i: usize| matches!(bytes.get(i), Some(b'e' | b'E')) && is_digit(i + 1 + exponent_sign(i));
+    let mut i = digits(0);
+    // but not `..`, as in `1..2`
+    if is_point(i) {
+        i = digits(i + 1);
+    }
+    if !bytes[..i].iter().any(u8::is_ascii_digit) {
+        return None;
+    }
+    if is_exponent(i) {
+        i = digits(i + 1 + exponent_sign(i));
+    } else if matches!(bytes.get(i), Some(b'e' | b'E')) {
+        // an exponent without digits, like `1e` or `1e+`, unless the `e`
+        // starts a word, as in `2em`
+        if exponent_sign(i) == 1 {
+            return Some(i + 2);
+        }
+        if !s[i + 1..].starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return Some(i + 1);
+        }
+    }
+    if is_point(i) || bytes.get(i) == Some(&b'_') || is_exponent(i) {
+        while bytes
+            .get(i)
+            .is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'_')
+            || is_point(i)
+        {
+            i += 1;
+        }
+    }
+    Some(i)
+}
+
+/// Parses a number like `1.5`, `1_000`, `0xFF`, `0o17` or `-0b1010`, where `_`
+/// can separate digits
+pub fn parse_number(s: &str) -> Option<f64> {
+    let (sign, digits) = match s.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, s),
+    };
+    if digits.contains('_') {
+        // only between digits, so identifiers like `in_f` aren't numbers
+        let bytes = digits.as_bytes();
+        let between_digits = |i: usize| {
+            i > 0
+                && bytes[i - 1].is_ascii_alphanumeric()
+                && bytes.get(i + 1).is_some_and(u8::is_ascii_alphanumeric)
+        };
+        if !(bytes[0].is_ascii_digit() || bytes[0] == b'.')
+            || !(0..bytes.len()).all(|i| bytes[i] != b'_' || between_digits(i))
+        {
+            return None;
+        }
+    }
+    let digits = digits.replace('_', "");
+    let radix = match digits.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => return s.replace('_', "").parse().ok(),
+    };
+    if !digits[2..].bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+    u64::from_str_radix(&digits[2..], radix)
+        .ok()
+        .map(|n| sign * n as f64)
+}
+
+/// Parses an integer literal like `0xFFFFFFFFFFFFFFFF` that's too big to be
+/// exact as a float, so it can be kept exact with a word size
+pub fn parse_big_integer(s: &str) -> Option<i128> {
+    // the same literals as `parse_number`, e.g. with `_` only between digits
+    if parse_number(s)?.abs() <= math::MAX_EXACT {
+        return None;
+    }
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let digits = digits.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, &digits[..]),
+    };
+    if !digits.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let n = i128::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -n } else { n })
+}
+
+/// Splits on whitespace, keeping string literals like `"a b"` in one token and
+/// making brackets, braces and semicolons tokens of their own
+pub fn split_tokens(s: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c.is_whitespace() {
+            if let Some(start) = start.take() {
+                tokens.push(&s[start..i]);
+            }
+        } else if "[]{};".contains(c) {
+            if let Some(start) = start.take() {
+                tokens.push(&s[start..i]);
+            }
+            tokens.push(&s[i..i + 1]);
         } else {
-            if last_was_number || last_was_parenthesis {
-                result.push(' ');
+            start.get_or_insert(i);
+            in_string = c == '"';
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&s[start..]);
+    }
+    tokens
+}
+
+/// The byte offset of `token` in `s`, which it's a slice of, like the tokens
+/// [`split_tokens`] returns
+pub fn offset_in(s: &str, token: &str) -> usize {
+    token.as_ptr() as usize - s.as_ptr() as usize
+}
+
+/// An error in a line of code, at the byte offset of the token it's about
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineError {
+    pub message: String,
+    pub offset: usize,
+}
+
+impl LineError {
+    /// Puts what was being done, like `Error parsing RPN`, before the message
+    pub fn context(self, context: &str) -> LineError {
+        LineError {
+            message: format!("{}: {}", context, self.message),
+            ..self
+        }
+    }
+}
+
+/// An error about a whole line, which is reported where it starts
+impl From<String> for LineError {
+    fn from(message: String) -> Self {
+        LineError { message, offset: 0 }
+    }
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Parses a quoted string literal like `"say \"hi\"\n"`, or returns `None` if
+/// `s` isn't one
+pub fn parse_string_literal(s: &str) -> Option<Result<String, String>> {
+    let mut chars = s.strip_prefix('"')?.chars();
+    let mut result = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.as_str().is_empty() => return Some(Ok(result)),
+            '"' => return Some(Err(format!("Invalid String: {}", s))),
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(c) => result.push(c),
+                None => break,
+            },
+            _ => result.push(c),
+        }
+    }
+    Some(Err(format!("Unterminated String: {}", s)))
+}
+
+/// Removes the `#` or `//` comment at the end of a line, if there's one outside
+/// of string literals
+pub fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
             }
-            result.push(c);
-            last_was_number = false;
-            last_was_operator = true;
-            last_was_parenthesis = false;
+        } else if c == '"' {
+            in_string = true;
+        } else if c == '#' || line[i..].starts_with("//") {
+            return &line[..i];
         }
     }
-    result
+    line
 }
 
 // logs and returns a singular value
@@ -47,3 +388,67 @@ macro_rules! log {
         e
     }};
 }
+
+mod test {
+    #![allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("1.5"), Some(1.5));
+        assert_eq!(parse_number("1_000_000"), Some(1e6));
+        assert_eq!(parse_number("0xFF"), Some(255.0));
+        assert_eq!(parse_number("0xff_ff"), Some(65535.0));
+        assert_eq!(parse_number("-0b1010"), Some(-10.0));
+        assert_eq!(parse_number("0o17"), Some(15.0));
+        assert_eq!(parse_number("0xFFFFFFFFFFFFFFFF"), Some(u64::MAX as f64));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("0xG"), None);
+        assert_eq!(parse_number("0b102"), None);
+        assert_eq!(parse_number("in_f"), None);
+        assert_eq!(parse_number("_1"), None);
+        assert_eq!(parse_number("1_"), None);
+        assert_eq!(parse_number("1__0"), None);
+        assert_eq!(parse_number("1.5e-3"), Some(0.0015));
+        assert_eq!(parse_number(".5_5"), Some(0.55));
+        assert_eq!(parse_number("inf"), Some(f64::INFINITY));
+        assert!(parse_number("nan").unwrap().is_nan());
+        assert_eq!(parse_number("1.2.3"), None);
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_whitespace("0xFF+1_000"), "0xFF + 1_000");
+        assert_eq!(normalize_whitespace("2*-3"), "2 * - 3");
+        assert_eq!(normalize_whitespace("x_1"), "x_1");
+        assert_eq!(normalize_whitespace("1e-5*2E+3"), "1e-5 * 2E+3");
+        assert_eq!(normalize_whitespace(".5+1_000.25"), ".5 + 1_000.25");
+        assert_eq!(normalize_whitespace("2em-1"), "2 em - 1");
+        assert_eq!(normalize_whitespace("1.2.3+1"), "1.2.3 + 1");
+        assert_eq!(normalize_whitespace("1..2.5"), "1 .. 2.5");
+        assert_eq!(normalize_whitespace("x1e5"), "x1e5");
+        assert_eq!(normalize_whitespace("_x+1"), "_x + 1");
+        assert_eq!(normalize_whitespace("3!+!x!=1"), "3 ! + ! x != 1");
+        assert_eq!(normalize_whitespace("x²+50%*2"), "x ² + 50 % * 2");
+        assert_eq!(
+            normalize_whitespace_with_offsets("2*-3"),
+            ("2 * - 3".to_string(), vec![0, 1, 1, 2, 2, 3, 3])
+        );
+        assert_eq!(
+            normalize_whitespace_with_offsets("\"é\"+1"),
+            ("\"é\" + 1".to_string(), vec![0, 1, 2, 3, 4, 4, 5, 5])
+        );
+    }
+
+    #[test]
+    fn test_strip_comment() {
+        assert_eq!(strip_comment("1 2 + # three"), "1 2 + ");
+        assert_eq!(strip_comment("1 + 2 // three"), "1 + 2 ");
+        assert_eq!(
+            strip_comment(r#""a # b" "c // d\"" ++"#),
+            r#""a # b" "c // d\"" ++"#
+        );
+        assert_eq!(strip_comment("1 / 2"), "1 / 2");
+        assert_eq!(strip_comment("# all of it"), "");
+    }
+}